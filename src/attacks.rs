@@ -1,6 +1,8 @@
+use std::sync::Once;
+
 use crate::{
     board::Board,
-    core_types::{Piece, SquareIdx, BB},
+    core_types::{Color, Piece, SquareIdx, BB},
 };
 pub const WALL_RIGHT: u64 = 0x8080808080808080;
 pub const WALL_LEFT: u64 = 0x101010101010101;
@@ -575,7 +577,13 @@ pub fn get_bishop_moves(bishop: BB, pieces: BB) -> BB {
     let magic_idx = garbadge >> offset;
     BB(unsafe { BISHOP_MOVES[bishop_idx][magic_idx as usize] })
 }
+/// Fills the slider tables. Only the first call does anything: after it the
+/// tables are never written again, so any number of threads may read them.
 pub fn init_magics(gen_magics: bool) {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| fill_magics(gen_magics));
+}
+fn fill_magics(gen_magics: bool) {
     gen_rook_potential_moves();
     gen_bishop_potential_moves();
     for i in 0..64 {
@@ -611,12 +619,27 @@ pub fn init_magics(gen_magics: bool) {
         println!("}}");
     }
 }
+/// Squares attacked by a single pawn of `color` standing on `sq`
+pub fn pawn_attacks(color: Color, sq: BB) -> BB {
+    BB(PAWN_CAPS[color as usize][sq.as_idx().0 as usize])
+}
+
+/// Squares attacked by all the `pawns` of `color` at once
+pub fn pawn_attacks_bb(color: Color, pawns: BB) -> BB {
+    let left = pawns.0 & !WALL_LEFT;
+    let right = pawns.0 & !WALL_RIGHT;
+    match color {
+        Color::White => BB(left << 7 | right << 9),
+        Color::Black => BB(left >> 9 | right >> 7),
+    }
+}
+
 impl Board {
     pub fn get_attacks(&self, piece: Piece, sq: BB) -> BB {
         let pieces = self.side[0] | self.side[1];
         let sq_idx = sq.as_idx();
         match piece {
-            Piece::Pawn => BB(PAWN_CAPS[self.color as usize][sq_idx.0 as usize]),
+            Piece::Pawn => pawn_attacks(self.color, sq),
             Piece::Knight => BB(KNIGHT_TABLE[sq_idx.0 as usize]),
             Piece::Bishop => get_bishop_moves(sq, pieces),
             Piece::Rook => get_rook_moves(sq, pieces),
@@ -624,4 +647,73 @@ impl Board {
             Piece::King => BB(KING_TABLE[usize::from(sq_idx)]),
         }
     }
+    pub fn piece_on(&self, sq: BB) -> Option<Piece> {
+        (0..6)
+            .map(Piece::from_u8)
+            .find(|p| !(self.pieces[*p as usize] & sq).empty())
+    }
+    /// Pieces of both colors attacking `sq`, sliders look through everything not in `occupied`
+    pub fn attackers_to(&self, sq: BB, occupied: BB) -> BB {
+        let white = self.side[Color::White as usize];
+        let black = self.side[Color::Black as usize];
+        let pawns = self.pieces[Piece::Pawn as usize];
+        let diagonal = self.pieces[Piece::Bishop as usize] | self.pieces[Piece::Queen as usize];
+        let straight = self.pieces[Piece::Rook as usize] | self.pieces[Piece::Queen as usize];
+        let sq_idx = sq.as_idx().0 as usize;
+        let attackers = (pawn_attacks(Color::White, sq) & pawns & black)
+            | (pawn_attacks(Color::Black, sq) & pawns & white)
+            | (BB(KNIGHT_TABLE[sq_idx]) & self.pieces[Piece::Knight as usize])
+            | (BB(KING_TABLE[sq_idx]) & self.pieces[Piece::King as usize])
+            | (get_bishop_moves(sq, occupied) & diagonal)
+            | (get_rook_moves(sq, occupied) & straight);
+        attackers & occupied
+    }
+    /// Every square attacked by the pieces of `color`, independent of the side to move
+    pub fn attacks_by(&self, color: Color) -> BB {
+        let pieces = self.side[0] | self.side[1];
+        let own = self.side[color as usize];
+        let mut attacks = pawn_attacks_bb(color, self.pieces[Piece::Pawn as usize] & own);
+        for sq in self.pieces[Piece::Knight as usize] & own {
+            attacks |= BB(KNIGHT_TABLE[sq.as_idx().0 as usize]);
+        }
+        let diagonal = self.pieces[Piece::Bishop as usize] | self.pieces[Piece::Queen as usize];
+        for sq in diagonal & own {
+            attacks |= get_bishop_moves(sq, pieces);
+        }
+        let straight = self.pieces[Piece::Rook as usize] | self.pieces[Piece::Queen as usize];
+        for sq in straight & own {
+            attacks |= get_rook_moves(sq, pieces);
+        }
+        for sq in self.pieces[Piece::King as usize] & own {
+            attacks |= BB(KING_TABLE[sq.as_idx().0 as usize]);
+        }
+        attacks
+    }
+}
+
+#[test]
+fn test_pawn_attacks_bb() {
+    for color in [Color::White, Color::Black] {
+        let pawns = BB(0x00ff_8100_0000_81ff & !(WALL_UP | WALL_DOWN)) | BB(0x0000_0024_4200_0000);
+        let mut expected = BB(0);
+        for sq in pawns {
+            expected |= pawn_attacks(color, sq);
+        }
+        assert!(pawn_attacks_bb(color, pawns) == expected);
+    }
+}
+#[test]
+fn test_attacks_by() {
+    init_magics(false);
+    let mut b = Board::new();
+    crate::fen::load_fen(&mut b, "4k3/8/8/4p3/8/8/8/4K2R w K - 0 1").unwrap();
+    let black = b.attacks_by(Color::Black);
+    // e5 pawn hits d4 and f4 even though white is to move
+    assert!(!(black & BB(1 << 27)).empty());
+    assert!(!(black & BB(1 << 29)).empty());
+    assert!((black & BB(1 << 28)).empty());
+    let white = b.attacks_by(Color::White);
+    // h1 rook sees the whole h file and up to the king on e1
+    assert!(!(white & BB(1 << 63)).empty());
+    assert!(!(white & BB(1 << 5)).empty());
 }
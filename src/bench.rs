@@ -0,0 +1,63 @@
+use std::time::Instant;
+
+use crate::{
+    board::Board,
+    fen::load_fen,
+    limits::SearchLimits,
+    nnue::Network,
+    ordering::History,
+    search::{score_to_uci, Search, SearchOptions, Signals},
+    tt::{TranspositionTable, DEFAULT_HASH_MB},
+    weights::Weights,
+};
+
+pub const DEFAULT_BENCH_DEPTH: u8 = 10;
+
+const BENCH_POSITIONS: [&str; 10] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "1k6/2pp4/1p2p3/4q3/1P2B3/2PK1R2/8/8 w - - 0 46",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+];
+
+/// Searches every bench position to `depth` in deterministic mode and prints
+/// the total node count. Any change that doesn't mean to change the search
+/// must leave it the same.
+pub fn bench(depth: u8, options: SearchOptions, net: Option<&Network>, weights: &Weights) {
+    let options = SearchOptions {
+        deterministic: true,
+        multi_pv: 1,
+        ..options
+    };
+    let tt = TranspositionTable::new(DEFAULT_HASH_MB);
+    let signals = Signals::default();
+    let mut histories = vec![History::new()];
+    let start = Instant::now();
+    let mut nodes = 0;
+    for fen in BENCH_POSITIONS {
+        let mut b = Board::new();
+        load_fen(&mut b, fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        let histories = &mut histories;
+        let result = Search::search(limits, b, &tt, &signals, options, histories, net, weights);
+        println!(
+            "info string {fen}: depth {} score {} bestmove {} nodes {}",
+            result.depth,
+            score_to_uci(result.score),
+            result.best.map_or("0000".to_owned(), |m| m.as_text()),
+            result.nodes
+        );
+        nodes += result.nodes;
+    }
+    let nps = nodes as u128 * 1000 / start.elapsed().as_millis().max(1);
+    println!("{nodes} nodes {nps} nps");
+}
@@ -55,7 +55,7 @@ impl Piece {
         }
     }
 }
-#[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Default)]
 pub struct BB(pub u64);
 
 impl BB {
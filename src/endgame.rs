@@ -0,0 +1,306 @@
+use crate::{
+    board::Board,
+    core_types::{Color, Piece, BB},
+    kpk,
+    pawns::{distance, relative_rank},
+    see::SEE_VALUES,
+    weights::Param,
+};
+
+/// Score of an endgame that is won no matter the details, still well below
+/// the mate scores
+pub const KNOWN_WIN: i32 = 10_000;
+/// `endgame_scale` of a position that plays out normally
+pub const SCALE_NORMAL: i32 = 64;
+
+/// `endgame_scale` of the drawish endings, out of `SCALE_NORMAL`, and the
+/// bonuses that make progress in the `known_endgame`s
+#[derive(Clone, Copy, Debug)]
+pub struct EndgameWeights {
+    /// No pawns and a rook or more, only a minor piece ahead
+    pub minor_ahead: i32,
+    /// Opposite colored bishops and nothing else
+    pub opposite_bishops_only: i32,
+    /// Opposite colored bishops with other pieces
+    pub opposite_bishops: i32,
+    /// For the losing king per square it is away from the center
+    pub push_to_edge: i32,
+    /// For the winning king per square it is closer to the losing one
+    pub push_close: i32,
+    /// For the losing king per square closer to the bishop's corner in KBNK
+    pub kbnk_corner: i32,
+    /// For a won KPK per rank the pawn has advanced
+    pub kpk_rank: i32,
+}
+
+impl EndgameWeights {
+    pub const DEFAULT: EndgameWeights = EndgameWeights {
+        minor_ahead: 16,
+        opposite_bishops_only: 22,
+        opposite_bishops: 46,
+        push_to_edge: 20,
+        push_close: 10,
+        kbnk_corner: 40,
+        kpk_rank: 20,
+    };
+
+    pub fn params<'a>(&'a mut self, prefix: &str, params: &mut Vec<(String, &'a mut i32)>) {
+        self.minor_ahead
+            .visit(format!("{prefix}minor_ahead"), params);
+        self.opposite_bishops_only
+            .visit(format!("{prefix}opposite_bishops_only"), params);
+        self.opposite_bishops
+            .visit(format!("{prefix}opposite_bishops"), params);
+        self.push_to_edge
+            .visit(format!("{prefix}push_to_edge"), params);
+        self.push_close.visit(format!("{prefix}push_close"), params);
+        self.kbnk_corner
+            .visit(format!("{prefix}kbnk_corner"), params);
+        self.kpk_rank.visit(format!("{prefix}kpk_rank"), params);
+    }
+}
+
+const DARK_SQUARES: u64 = 0xaa55_aa55_aa55_aa55;
+
+/// Bonus for the losing king being close to the edge, by square
+fn push_to_edge(w: &EndgameWeights, sq: u8) -> i32 {
+    let (file, rank) = ((sq % 8) as i32, (sq / 8) as i32);
+    let from_center = (3 - file).max(file - 4) + (3 - rank).max(rank - 4);
+    w.push_to_edge * from_center
+}
+
+/// Bonus for the winning king being close to the losing one
+fn push_close(w: &EndgameWeights, a: u8, b: u8) -> i32 {
+    w.push_close * (7 - distance(a, b))
+}
+
+fn on_dark(sq: u8) -> bool {
+    DARK_SQUARES & 1 << sq != 0
+}
+
+impl Board {
+    fn count(&self, color: Color, piece: Piece) -> u32 {
+        (self.pieces[piece as usize] & self.side[color as usize])
+            .0
+            .count_ones()
+    }
+
+    fn king_sq(&self, color: Color) -> u8 {
+        (self.pieces[Piece::King as usize] & self.side[color as usize])
+            .as_idx()
+            .0
+    }
+
+    /// Knights, bishops, rooks and queens of `color` by their exchange value
+    fn piece_material(&self, color: Color) -> i32 {
+        [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+            .into_iter()
+            .map(|p| self.count(color, p) as i32 * SEE_VALUES[p as usize])
+            .sum()
+    }
+
+    /// Evaluation of the endgames against a bare king, from White's side,
+    /// which replaces the normal one. `None` for everything else, pawns
+    /// included except for KPK: rook pawns and wrong bishops draw, which
+    /// `endgame_scale` takes care of.
+    pub fn known_endgame(&self, w: &EndgameWeights) -> Option<i32> {
+        for strong in [Color::White, Color::Black] {
+            let weak = strong.opposite();
+            if self.side[weak as usize]
+                != self.pieces[Piece::King as usize] & self.side[weak as usize]
+            {
+                continue;
+            }
+            let pawns = self.count(strong, Piece::Pawn);
+            let knights = self.count(strong, Piece::Knight);
+            let bishops = self.pieces[Piece::Bishop as usize] & self.side[strong as usize];
+            let heavy = self.count(strong, Piece::Rook) + self.count(strong, Piece::Queen);
+            let both_colors =
+                !(bishops & BB(DARK_SQUARES)).empty() && !(bishops & BB(!DARK_SQUARES)).empty();
+
+            let score = if pawns == 1 && self.piece_material(strong) == 0 {
+                self.kpk(w, strong)
+            } else if pawns > 0 {
+                return None;
+            } else if heavy == 0 && knights == 1 && bishops.0.count_ones() == 1 {
+                self.kbnk(w, strong)
+            } else if heavy > 0 || both_colors || (knights > 0 && !bishops.empty()) {
+                self.kxk(w, strong)
+            } else {
+                // Knights or bishops of one color can't mate on their own
+                0
+            };
+            return Some(match strong {
+                Color::White => score,
+                Color::Black => -score,
+            });
+        }
+        None
+    }
+
+    /// Enough material to mate: drive the king to the edge and come closer
+    fn kxk(&self, w: &EndgameWeights, strong: Color) -> i32 {
+        let (ours, theirs) = (self.king_sq(strong), self.king_sq(strong.opposite()));
+        KNOWN_WIN
+            + self.piece_material(strong)
+            + push_to_edge(w, theirs)
+            + push_close(w, ours, theirs)
+    }
+
+    /// Bishop and knight only mate in a corner of the bishop's color
+    fn kbnk(&self, w: &EndgameWeights, strong: Color) -> i32 {
+        let (ours, theirs) = (self.king_sq(strong), self.king_sq(strong.opposite()));
+        let bishop = (self.pieces[Piece::Bishop as usize] & self.side[strong as usize])
+            .as_idx()
+            .0;
+        let corners = if on_dark(bishop) { [0, 63] } else { [7, 56] };
+        let to_corner = corners
+            .map(|c| distance(theirs, c))
+            .into_iter()
+            .min()
+            .unwrap();
+        KNOWN_WIN
+            + self.piece_material(strong)
+            + w.kbnk_corner * (7 - to_corner)
+            + push_to_edge(w, theirs) / 2
+            + push_close(w, ours, theirs)
+    }
+
+    /// King and pawn against king, exact from the bitbase
+    fn kpk(&self, w: &EndgameWeights, strong: Color) -> i32 {
+        let weak = strong.opposite();
+        let pawn = (self.pieces[Piece::Pawn as usize] & self.side[strong as usize])
+            .as_idx()
+            .0;
+        let (ours, theirs) = (self.king_sq(strong), self.king_sq(weak));
+        // The bitbase has the pawn going up the board
+        let flip = |sq: u8| match strong {
+            Color::White => sq,
+            Color::Black => sq ^ 56,
+        };
+        let stm = if self.color == strong {
+            Color::White
+        } else {
+            Color::Black
+        };
+        if kpk::probe(flip(ours), flip(pawn), flip(theirs), stm) {
+            KNOWN_WIN
+                + SEE_VALUES[Piece::Pawn as usize]
+                + w.kpk_rank * relative_rank(strong, pawn) as i32
+        } else {
+            0
+        }
+    }
+
+    /// How much of the endgame score the side ahead gets to keep, out of
+    /// `SCALE_NORMAL`, for endings that are much more drawish than the
+    /// material says
+    pub fn endgame_scale(&self, w: &EndgameWeights, strong: Color) -> i32 {
+        let weak = strong.opposite();
+        let pawns = self.pieces[Piece::Pawn as usize];
+        let our_pawns = pawns & self.side[strong as usize];
+        let (ours, theirs) = (self.piece_material(strong), self.piece_material(weak));
+
+        // Without pawns a minor piece more isn't enough to win
+        if our_pawns.empty() && ours - theirs <= SEE_VALUES[Piece::Bishop as usize] {
+            return if ours < SEE_VALUES[Piece::Rook as usize] {
+                0
+            } else {
+                w.minor_ahead
+            };
+        }
+
+        let bishops = self.pieces[Piece::Bishop as usize];
+        let our_bishops = bishops & self.side[strong as usize];
+        let their_bishops = bishops & self.side[weak as usize];
+        let bishop_only =
+            ours == SEE_VALUES[Piece::Bishop as usize] && our_bishops.0.count_ones() == 1;
+
+        // Rook pawns alone or with the bishop of the wrong color for the
+        // queening corner, which the defending king holds
+        let rook_files = BB(0x8181_8181_8181_8181);
+        if (bishop_only || ours == 0) && (our_pawns & !rook_files).empty() {
+            let file = our_pawns.as_idx().0 % 8;
+            let all_on_file = (our_pawns & BB(0x0101_0101_0101_0101 << file)) == our_pawns;
+            let queening = match strong {
+                Color::White => 56 + file,
+                Color::Black => file,
+            };
+            let wrong_bishop = ours == 0 || on_dark(our_bishops.as_idx().0) != on_dark(queening);
+            if all_on_file && wrong_bishop && distance(self.king_sq(weak), queening) <= 1 {
+                return 0;
+            }
+        }
+
+        // Opposite colored bishops, even more drawish without other pieces
+        if our_bishops.0.count_ones() == 1
+            && their_bishops.0.count_ones() == 1
+            && on_dark(our_bishops.as_idx().0) != on_dark(their_bishops.as_idx().0)
+        {
+            return if bishop_only && theirs == ours {
+                w.opposite_bishops_only
+            } else {
+                w.opposite_bishops
+            };
+        }
+        SCALE_NORMAL
+    }
+}
+
+#[test]
+fn test_endgames() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let known = |fen: &str| {
+        let mut b = Board::new();
+        load_fen(&mut b, fen).unwrap();
+        b.known_endgame(&EndgameWeights::DEFAULT)
+    };
+    let scale = |fen: &str, strong: Color| {
+        let mut b = Board::new();
+        load_fen(&mut b, fen).unwrap();
+        b.endgame_scale(&EndgameWeights::DEFAULT, strong)
+    };
+
+    // KRK: the closer to the edge the better, same from the other side
+    let center = known("8/8/8/3k4/8/8/8/R3K3 w - - 0 1").unwrap();
+    let edge = known("3k4/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+    assert!(center > KNOWN_WIN && edge > center);
+    assert_eq!(known("r3k3/8/8/8/8/8/8/3K4 w - - 0 1").unwrap(), -edge);
+    // KBNK with a dark squared bishop mates on a1 and h8, not a8 and h1
+    let right = known("8/8/8/8/8/1K6/8/kNB5 w - - 0 1").unwrap();
+    let wrong = known("k7/8/1K6/8/8/8/8/1NB5 w - - 0 1").unwrap();
+    assert!(right > wrong);
+    assert_eq!(known("8/8/3k4/8/8/8/8/NN2K3 w - - 0 1"), Some(0));
+    // KPK: the pawn outruns the king, and the king in front of a rook pawn,
+    // from both sides. Nothing known with material on both sides.
+    assert!(known("8/8/8/P7/8/8/8/k1K5 w - - 0 1").unwrap() > KNOWN_WIN);
+    assert_eq!(known("k7/8/8/P7/8/8/8/2K5 w - - 0 1"), Some(0));
+    assert!(known("5K1k/8/8/8/p7/8/8/8 w - - 0 1").unwrap() < -KNOWN_WIN);
+    assert_eq!(known("8/8/8/4p3/4k3/8/4K3/8 b - - 0 1"), Some(0));
+    assert!(known("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1").unwrap() < -KNOWN_WIN);
+    assert_eq!(known("8/p7/8/8/8/8/8/R3K2k w - - 0 1"), None);
+    // More pawns, or pawns with pieces, are left to the normal eval and its
+    // scaling: doubled rook pawns and the wrong bishop draw
+    assert_eq!(known("k7/8/8/8/P7/P7/8/7K w - - 0 1"), None);
+    assert_eq!(scale("k7/8/8/8/P7/P7/8/7K w - - 0 1", Color::White), 0);
+    assert_eq!(known("7k/8/7P/8/8/8/8/3BK3 w - - 0 1"), None);
+    assert_eq!(scale("7k/8/7P/8/8/8/8/3BK3 w - - 0 1", Color::White), 0);
+    assert_eq!(known("7k/8/8/8/8/8/P7/R3K3 w - - 0 1"), None);
+
+    assert_eq!(scale("8/8/4k3/8/8/8/8/2B1KN2 w - - 0 1", Color::White), 64);
+    assert_eq!(scale("8/8/4k3/8/4n3/8/8/2B1K3 w - - 0 1", Color::White), 0);
+    assert_eq!(scale("8/8/4k3/8/4b3/8/8/R3K3 w - - 0 1", Color::White), 16);
+    // Opposite colored bishops
+    assert_eq!(
+        scale("8/5p2/4bk2/8/8/5PP1/8/2B1K3 w - - 0 1", Color::White),
+        22
+    );
+    assert_eq!(
+        scale("8/5p2/4bk2/8/8/5PP1/8/R1B1K2r w - - 0 1", Color::White),
+        46
+    );
+    // h pawns and a light squared bishop, h8 is dark
+    assert_eq!(scale("7k/8/7P/8/8/8/7P/3BK3 w - - 0 1", Color::White), 0);
+    assert_eq!(scale("7k/8/7P/8/8/8/7P/2B1K3 w - - 0 1", Color::White), 64);
+}
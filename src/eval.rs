@@ -0,0 +1,567 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use crate::{
+    board::Board,
+    core_types::Color,
+    endgame::SCALE_NORMAL,
+    pawns::{pawn_structure, PawnTable},
+    pieces::Activity,
+    weights::{Param, Weights},
+};
+
+/// A midgame and an endgame score, blended by the game phase at the very end
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct S(pub i32, pub i32);
+
+impl Add for S {
+    type Output = S;
+    fn add(self, rhs: S) -> S {
+        S(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+impl Sub for S {
+    type Output = S;
+    fn sub(self, rhs: S) -> S {
+        S(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+impl AddAssign for S {
+    fn add_assign(&mut self, rhs: S) {
+        *self = *self + rhs;
+    }
+}
+impl SubAssign for S {
+    fn sub_assign(&mut self, rhs: S) {
+        *self = *self - rhs;
+    }
+}
+impl Neg for S {
+    type Output = S;
+    fn neg(self) -> S {
+        S(-self.0, -self.1)
+    }
+}
+impl Mul<i32> for S {
+    type Output = S;
+    fn mul(self, rhs: i32) -> S {
+        S(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+/// Piece-square tables from White's side, a8 first so they read like a board
+#[rustfmt::skip]
+const MG_PST: [[i32; 64]; 6] = [
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         98, 134,  61,  95,  68, 126,  34, -11,
+         -6,   7,  26,  31,  65,  56,  25, -20,
+        -14,  13,   6,  21,  23,  12,  17, -23,
+        -27,  -2,  -5,  12,  17,   6,  10, -25,
+        -26,  -4,  -4, -10,   3,   3,  33, -12,
+        -35,  -1, -20, -23, -15,  24,  38, -22,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    [
+       -167, -89, -34, -49,  61, -97, -15,-107,
+        -73, -41,  72,  36,  23,  62,   7, -17,
+        -47,  60,  37,  65,  84, 129,  73,  44,
+         -9,  17,  19,  53,  37,  69,  18,  22,
+        -13,   4,  16,  13,  28,  19,  21,  -8,
+        -23,  -9,  12,  10,  19,  17,  25, -16,
+        -29, -53, -12,  -3,  -1,  18, -14, -19,
+       -105, -21, -58, -33, -17, -28, -19, -23,
+    ],
+    [
+        -29,   4, -82, -37, -25, -42,   7,  -8,
+        -26,  16, -18, -13,  30,  59,  18, -47,
+        -16,  37,  43,  40,  35,  50,  37,  -2,
+         -4,   5,  19,  50,  37,  37,   7,  -2,
+         -6,  13,  13,  26,  34,  12,  10,   4,
+          0,  15,  15,  15,  14,  27,  18,  10,
+          4,  15,  16,   0,   7,  21,  33,   1,
+        -33,  -3, -14, -21, -13, -12, -39, -21,
+    ],
+    [
+         32,  42,  32,  51,  63,   9,  31,  43,
+         27,  32,  58,  62,  80,  67,  26,  44,
+         -5,  19,  26,  36,  17,  45,  61,  16,
+        -24, -11,   7,  26,  24,  35,  -8, -20,
+        -36, -26, -12,  -1,   9,  -7,   6, -23,
+        -45, -25, -16, -17,   3,   0,  -5, -33,
+        -44, -16, -20,  -9,  -1,  11,  -6, -71,
+        -19, -13,   1,  17,  16,   7, -37, -26,
+    ],
+    [
+        -28,   0,  29,  12,  59,  44,  43,  45,
+        -24, -39,  -5,   1, -16,  57,  28,  54,
+        -13, -17,   7,   8,  29,  56,  47,  57,
+        -27, -27, -16, -16,  -1,  17,  -2,   1,
+         -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+        -14,   2, -11,  -2,  -5,   2,  14,   5,
+        -35,  -8,  11,   2,   8,  15,  -3,   1,
+         -1, -18,  -9,  10, -15, -25, -31, -50,
+    ],
+    [
+        -65,  23,  16, -15, -56, -34,   2,  13,
+         29,  -1, -20,  -7,  -8,  -4, -38, -29,
+         -9,  24,   2, -16, -20,   6,  22, -22,
+        -17, -20, -12, -27, -30, -25, -14, -36,
+        -49,  -1, -27, -39, -46, -44, -33, -51,
+        -14, -14, -22, -46, -44, -30, -15, -27,
+          1,   7,  -8, -64, -43, -16,   9,   8,
+        -15,  36,  12, -54,   8, -28,  24,  14,
+    ],
+];
+
+#[rustfmt::skip]
+const EG_PST: [[i32; 64]; 6] = [
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+        178, 173, 158, 134, 147, 132, 165, 187,
+         94, 100,  85,  67,  56,  53,  82,  84,
+         32,  24,  13,   5,  -2,   4,  17,  17,
+         13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+          4,   7,  -6,   1,   0,  -5,  -1,  -8,
+         13,   8,   8,  10,  13,   0,   2,  -7,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    [
+        -58, -38, -13, -28, -31, -27, -63, -99,
+        -25,  -8, -25,  -2,  -9, -25, -24, -52,
+        -24, -20,  10,   9,  -1,  -9, -19, -41,
+        -17,   3,  22,  22,  22,  11,   8, -18,
+        -18,  -6,  16,  25,  16,  17,   4, -18,
+        -23,  -3,  -1,  15,  10,  -3, -20, -22,
+        -42, -20, -10,  -5,  -2, -20, -23, -44,
+        -29, -51, -23, -15, -22, -18, -50, -64,
+    ],
+    [
+        -14, -21, -11,  -8,  -7,  -9, -17, -24,
+         -8,  -4,   7, -12,  -3, -13,  -4, -14,
+          2,  -8,   0,  -1,  -2,   6,   0,   4,
+         -3,   9,  12,   9,  14,  10,   3,   2,
+         -6,   3,  13,  19,   7,  10,  -3,  -9,
+        -12,  -3,   8,  10,  13,   3,  -7, -15,
+        -14, -18,  -7,  -1,   4,  -9, -15, -27,
+        -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+    ],
+    [
+         13,  10,  18,  15,  12,  12,   8,   5,
+         11,  13,  13,  11,  -3,   3,   8,   3,
+          7,   7,   7,   5,   4,  -3,  -5,  -3,
+          4,   3,  13,   1,   2,   1,  -1,   2,
+          3,   5,   8,   4,  -5,  -6,  -8, -11,
+         -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+         -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+         -9,   2,   3,  -1,  -5, -13,   4, -20,
+    ],
+    [
+         -9,  22,  22,  27,  27,  19,  10,  20,
+        -17,  20,  32,  41,  58,  25,  30,   0,
+        -20,   6,   9,  49,  47,  35,  19,   9,
+          3,  22,  24,  45,  57,  40,  57,  36,
+        -18,  28,  19,  47,  31,  34,  39,  23,
+        -16, -27,  15,   6,   9,  17,  10,   5,
+        -22, -23, -30, -16, -16, -23, -36, -32,
+        -33, -28, -22, -43,  -5, -32, -20, -41,
+    ],
+    [
+        -74, -35, -18, -18, -11,  15,   4, -17,
+        -12,  17,  14,  17,  17,  38,  23,  11,
+         10,  17,  23,  15,  20,  45,  44,  13,
+         -8,  22,  24,  27,  26,  33,  26,   3,
+        -18,  -4,  21,  24,  27,  23,   9, -11,
+        -19,  -3,  11,  21,  23,  16,   7,  -9,
+        -27, -11,   4,  13,  14,   4,  -5, -17,
+        -53, -34, -21, -11, -28, -14, -24, -43,
+    ],
+];
+
+/// Material and piece-square tables, the part of the evaluation `Psqt` keeps
+/// up to date
+#[derive(Clone, Copy, Debug)]
+pub struct PsqtWeights {
+    pub mg_values: [i32; 6],
+    pub eg_values: [i32; 6],
+    pub mg_pst: [[i32; 64]; 6],
+    pub eg_pst: [[i32; 64]; 6],
+    /// How much every piece counts towards the midgame
+    pub phase: [i32; 6],
+}
+
+impl PsqtWeights {
+    pub const DEFAULT: PsqtWeights = PsqtWeights {
+        mg_values: [82, 337, 365, 477, 1025, 0],
+        eg_values: [94, 281, 297, 512, 936, 0],
+        mg_pst: MG_PST,
+        eg_pst: EG_PST,
+        phase: [0, 1, 1, 2, 4, 0],
+    };
+
+    pub fn params<'a>(&'a mut self, prefix: &str, params: &mut Vec<(String, &'a mut i32)>) {
+        self.mg_values.visit(format!("{prefix}mg_values"), params);
+        self.eg_values.visit(format!("{prefix}eg_values"), params);
+        self.mg_pst.visit(format!("{prefix}mg_pst"), params);
+        self.eg_pst.visit(format!("{prefix}eg_pst"), params);
+        self.phase.visit(format!("{prefix}phase"), params);
+    }
+
+    /// Phase of the starting position, anything above is treated as a pure
+    /// midgame
+    pub fn max_phase(&self) -> i32 {
+        let p = &self.phase;
+        (16 * p[0] + 4 * (p[1] + p[2] + p[3]) + 2 * (p[4] + p[5])).max(1)
+    }
+
+    /// Midgame and endgame value of a `color` `piece` on `sq`
+    fn value(&self, color: Color, piece: usize, sq: usize) -> S {
+        let idx = pst_idx(color, sq);
+        S(
+            self.mg_values[piece] + self.mg_pst[piece][idx],
+            self.eg_values[piece] + self.eg_pst[piece][idx],
+        )
+    }
+}
+
+/// Index into the tables for a piece of `color` on `sq` (a1 = 0)
+fn pst_idx(color: Color, sq: usize) -> usize {
+    match color {
+        Color::White => sq ^ 56,
+        Color::Black => sq,
+    }
+}
+
+/// Material and piece-square sums of both sides, kept up to date move by move
+/// so the search doesn't have to add them up at every leaf
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Psqt {
+    /// Indexed by color
+    score: [S; 2],
+    phase: i32,
+}
+
+impl Psqt {
+    /// Adds everything up from scratch
+    pub fn new(w: &PsqtWeights, b: &Board) -> Self {
+        let mut psqt = Psqt::default();
+        for color in [Color::White, Color::Black] {
+            for p in 0..6 {
+                let pieces = b.pieces[p] & b.side[color as usize];
+                for sq in pieces {
+                    psqt.add(w, color, p, sq.as_idx().0 as usize);
+                }
+            }
+        }
+        psqt
+    }
+
+    fn add(&mut self, w: &PsqtWeights, color: Color, piece: usize, sq: usize) {
+        self.score[color as usize] += w.value(color, piece, sq);
+        self.phase += w.phase[piece];
+    }
+
+    fn remove(&mut self, w: &PsqtWeights, color: Color, piece: usize, sq: usize) {
+        self.score[color as usize] -= w.value(color, piece, sq);
+        self.phase -= w.phase[piece];
+    }
+
+    /// Applies the move that turned `before` into `after`. Only the squares that
+    /// changed are looked at, which handles captures, promotions, castling and
+    /// en passant alike.
+    pub fn update(&mut self, w: &PsqtWeights, before: &Board, after: &Board) {
+        for color in [Color::White, Color::Black] {
+            for p in 0..6 {
+                let old = before.pieces[p] & before.side[color as usize];
+                let new = after.pieces[p] & after.side[color as usize];
+                for sq in old & !new {
+                    self.remove(w, color, p, sq.as_idx().0 as usize);
+                }
+                for sq in new & !old {
+                    self.add(w, color, p, sq.as_idx().0 as usize);
+                }
+            }
+        }
+    }
+
+    /// 0 for a bare endgame up to `PsqtWeights::max_phase` with all the
+    /// pieces on the board
+    pub fn phase(&self, w: &PsqtWeights) -> i32 {
+        self.phase.clamp(0, w.max_phase())
+    }
+
+    /// From White's side
+    pub fn score(&self) -> S {
+        self.score[Color::White as usize] - self.score[Color::Black as usize]
+    }
+}
+
+/// Blends the midgame and endgame parts of `score` by `phase` out of
+/// `max_phase`
+pub fn taper(score: S, phase: i32, max_phase: i32) -> i32 {
+    (score.0 * phase + score.1 * (max_phase - phase)) / max_phase
+}
+
+impl Board {
+    /// The final score from White's side of a position whose terms add up to
+    /// `score`: the known endgame score if there is one, otherwise `score`
+    /// tapered with the endgame part scaled for drawish endings
+    pub fn endgame_adjusted(&self, w: &Weights, score: S, phase: i32) -> i32 {
+        if let Some(score) = self.known_endgame(&w.endgame) {
+            return score;
+        }
+        taper(
+            S(score.0, score.1 * self.scale_for(w, score) / SCALE_NORMAL),
+            phase,
+            w.psqt.max_phase(),
+        )
+    }
+
+    /// `endgame_scale` of the side `score` favors in the endgame
+    fn scale_for(&self, w: &Weights, score: S) -> i32 {
+        let strong = if score.1 >= 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        self.endgame_scale(&w.endgame, strong)
+    }
+}
+
+impl Board {
+    /// Static evaluation from the side to move's point of view, `psqt` and
+    /// `pawn_key` have to match the board and `psqt` has to come from `w`
+    pub fn evaluate(
+        &self,
+        w: &Weights,
+        psqt: &Psqt,
+        pawn_key: u64,
+        pawns: &mut PawnTable,
+        king_safety: bool,
+    ) -> i32 {
+        let mut score = psqt.score();
+        let entry = pawns.probe(self, &w.pawns, pawn_key);
+        score += entry.score;
+        let passed = entry.passed;
+        score += self.passed_pawn_race(&w.pawns, Color::White, passed[Color::White as usize])
+            - self.passed_pawn_race(&w.pawns, Color::Black, passed[Color::Black as usize]);
+        if king_safety {
+            score +=
+                self.king_safety(&w.king, Color::White) - self.king_safety(&w.king, Color::Black);
+        }
+        score += self.activity(&w.pieces, Color::White).total()
+            - self.activity(&w.pieces, Color::Black).total();
+        let score = self.endgame_adjusted(w, score, psqt.phase(&w.psqt));
+        match self.color {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+
+    /// The same evaluation as `evaluate`, term by term and without any caching
+    pub fn trace(&self, weights: &Weights, king_safety: bool) -> Trace {
+        let mut material = [S::default(); 2];
+        let mut pst = [S::default(); 2];
+        let mut pawns = [S::default(); 2];
+        let mut passed = [S::default(); 2];
+        let mut safety = [S::default(); 2];
+        let mut activity = [Activity::default(); 2];
+        let w = &weights.psqt;
+        for color in [Color::White, Color::Black] {
+            let c = color as usize;
+            for p in 0..6 {
+                for sq in self.pieces[p] & self.side[c] {
+                    let idx = pst_idx(color, sq.as_idx().0 as usize);
+                    material[c] += S(w.mg_values[p], w.eg_values[p]);
+                    pst[c] += S(w.mg_pst[p][idx], w.eg_pst[p][idx]);
+                }
+            }
+            let (structure, passers) = pawn_structure(self, &weights.pawns, color);
+            pawns[c] = structure;
+            passed[c] = self.passed_pawn_race(&weights.pawns, color, passers);
+            if king_safety {
+                safety[c] = self.king_safety(&weights.king, color);
+            }
+            activity[c] = self.activity(&weights.pieces, color);
+        }
+        let mut trace = Trace {
+            terms: vec![
+                ("Material", material),
+                ("PST", pst),
+                ("Pawns", pawns),
+                ("Passed", passed),
+                ("King safety", safety),
+                ("Mobility", activity.map(|a| a.mobility)),
+                ("Outposts", activity.map(|a| a.outposts)),
+                ("Bishop pair", activity.map(|a| a.bishop_pair)),
+                ("Rooks", activity.map(|a| a.rooks)),
+                ("Trapped", activity.map(|a| a.trapped)),
+            ],
+            phase: Psqt::new(w, self).phase(w),
+            max_phase: w.max_phase(),
+            scale: SCALE_NORMAL,
+            known: self.known_endgame(&weights.endgame),
+        };
+        trace.scale = self.scale_for(weights, trace.total());
+        trace
+    }
+}
+
+/// The evaluation split into its terms
+pub struct Trace {
+    /// Name and score of every term, indexed by color
+    pub terms: Vec<(&'static str, [S; 2])>,
+    pub phase: i32,
+    pub max_phase: i32,
+    /// Share of the endgame part kept, out of `SCALE_NORMAL`
+    pub scale: i32,
+    /// Score of a known endgame, which replaces everything else
+    pub known: Option<i32>,
+}
+
+impl Trace {
+    /// Sum of all the terms, from White's side
+    pub fn total(&self) -> S {
+        self.terms
+            .iter()
+            .fold(S::default(), |total, (_, [white, black])| {
+                total + *white - *black
+            })
+    }
+
+    /// The final score from White's side, what `evaluate` returns for White
+    pub fn score(&self) -> i32 {
+        if let Some(score) = self.known {
+            return score;
+        }
+        let total = self.total();
+        let total = S(total.0, total.1 * self.scale / SCALE_NORMAL);
+        taper(total, self.phase, self.max_phase)
+    }
+
+    /// One line per term, for the `trace` command
+    pub fn print(&self) {
+        for (name, [white, black]) in &self.terms {
+            println!(
+                "info string {name}: white {} {} black {} {}",
+                white.0, white.1, black.0, black.1
+            );
+        }
+        let total = self.total();
+        println!(
+            "info string Total: {} {} phase {} scale {} score {}",
+            total.0,
+            total.1,
+            self.phase,
+            self.scale,
+            self.score()
+        );
+    }
+
+    /// Every term for both colors and both phases, then the tapered score,
+    /// for the `eval` command
+    pub fn table(&self) -> String {
+        let mut table = String::new();
+        table += "        Term |    White    |    Black    |    Total\n";
+        table += "             |   MG    EG  |   MG    EG  |   MG    EG\n";
+        table += " ------------+-------------+-------------+------------\n";
+        let mut row = |name: &str, white: Option<S>, black: Option<S>, total: S| {
+            let cell = |s: Option<S>| match s {
+                Some(s) => format!("{:5} {:5}", s.0, s.1),
+                None => format!("{:>5} {:>5}", "-", "-"),
+            };
+            table += &format!(
+                " {name:>11} | {} | {} | {}\n",
+                cell(white),
+                cell(black),
+                cell(Some(total))
+            );
+        };
+        for (name, [white, black]) in &self.terms {
+            row(name, Some(*white), Some(*black), *white - *black);
+        }
+        row("Total", None, None, self.total());
+        table += &format!(
+            "\nPhase: {}/{}\nEndgame scale: {}/{SCALE_NORMAL}\n",
+            self.phase, self.max_phase, self.scale
+        );
+        if let Some(score) = self.known {
+            table += &format!("Known endgame: {score}\n");
+        }
+        table += &format!("Final evaluation: {} (White side)\n", self.score());
+        table
+    }
+}
+
+#[cfg(test)]
+fn mirror(b: &Board) -> Board {
+    use crate::core_types::BB;
+    let mut m = *b;
+    for p in 0..6 {
+        m.pieces[p] = BB(b.pieces[p].0.swap_bytes());
+    }
+    m.side = [BB(b.side[1].0.swap_bytes()), BB(b.side[0].0.swap_bytes())];
+    m.color = b.color.opposite();
+    m.castle = (b.castle & 0b1100) >> 2 | (b.castle & 0b0011) << 2;
+    m
+}
+
+#[test]
+fn test_eval_symmetry() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let mut pawns = PawnTable::new();
+    let w = Weights::DEFAULT;
+    let mut white_eval = |b: &Board| {
+        let eval = b.evaluate(&w, &Psqt::new(&w.psqt, b), b.pawn_hash(), &mut pawns, true);
+        match b.color {
+            Color::White => eval,
+            Color::Black => -eval,
+        }
+    };
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "1k6/2pp4/1p2p3/4q3/1P2B3/2PK1R2/8/8 w - - 0 46",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+    ] {
+        let mut b = Board::new();
+        load_fen(&mut b, fen).unwrap();
+        assert_eq!(white_eval(&b), -white_eval(&mirror(&b)), "{fen}");
+    }
+}
+
+#[test]
+fn test_psqt_incremental() {
+    let w = &Weights::DEFAULT.psqt;
+    let start = |b: &Board| Psqt::new(w, b);
+    crate::utils::walk_moves(3, start, &mut |before, after, psqt| {
+        let mut psqt = psqt;
+        psqt.update(w, before, after);
+        assert_eq!(psqt, Psqt::new(w, after));
+        psqt
+    });
+}
+
+#[test]
+fn test_trace() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let mut pawns = PawnTable::new();
+    let w = Weights::DEFAULT;
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ] {
+        let mut b = Board::new();
+        load_fen(&mut b, fen).unwrap();
+        let psqt = Psqt::new(&w.psqt, &b);
+        for king_safety in [false, true] {
+            let trace = b.trace(&w, king_safety);
+            let eval = b.evaluate(&w, &psqt, b.pawn_hash(), &mut pawns, king_safety);
+            assert_eq!(trace.score(), eval, "{fen}");
+        }
+        let table = b.trace(&w, true).table();
+        let eval = b.evaluate(&w, &psqt, b.pawn_hash(), &mut pawns, true);
+        assert!(table.ends_with(&format!("Final evaluation: {eval} (White side)\n")));
+    }
+}
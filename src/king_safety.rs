@@ -0,0 +1,194 @@
+use crate::{
+    attacks::{get_bishop_moves, get_rook_moves, KING_TABLE, KNIGHT_TABLE},
+    board::Board,
+    core_types::{Color, Piece, BB},
+    eval::S,
+    pawns::{file_bb, forward_ranks},
+    weights::Param,
+};
+
+/// All in danger units, turned into a penalty by the `danger` table once
+/// added up
+#[derive(Clone, Copy, Debug)]
+pub struct KingWeights {
+    /// Per attacked king zone square, by piece
+    pub attack_weight: [i32; 6],
+    /// For being able to give a check on a square we don't defend, by piece
+    pub safe_check: [i32; 6],
+    /// For a shield pawn by how far in front of the king it is, the last one
+    /// for no pawn at all
+    pub shield: [i32; 4],
+    /// For an enemy pawn storming by how many ranks it is away from the king,
+    /// a pawn right in front is blocked and harmless
+    pub storm: [i32; 5],
+    /// For a file next to the king without our pawns, and more without any
+    /// pawns
+    pub half_open_file: i32,
+    pub open_file: i32,
+    /// Midgame penalty by danger units, slow at first so a single attacker
+    /// barely counts, then quickly growing
+    pub danger: [i32; DANGER_SIZE],
+    /// Units beyond this don't make it any worse, at most the end of the
+    /// table
+    pub max_danger_units: i32,
+    /// The endgame penalty is the midgame one divided by that
+    pub danger_eg_divisor: i32,
+}
+
+impl KingWeights {
+    pub const DEFAULT: KingWeights = KingWeights {
+        attack_weight: [0, 2, 2, 3, 5, 0],
+        safe_check: [0, 8, 5, 8, 6, 0],
+        shield: [0, 0, 2, 4],
+        storm: [0, 0, 4, 2, 1],
+        half_open_file: 2,
+        open_file: 3,
+        danger: DANGER,
+        max_danger_units: DANGER_SIZE as i32 - 1,
+        danger_eg_divisor: 8,
+    };
+
+    pub fn params<'a>(&'a mut self, prefix: &str, params: &mut Vec<(String, &'a mut i32)>) {
+        self.attack_weight
+            .visit(format!("{prefix}attack_weight"), params);
+        self.safe_check.visit(format!("{prefix}safe_check"), params);
+        self.shield.visit(format!("{prefix}shield"), params);
+        self.storm.visit(format!("{prefix}storm"), params);
+        self.half_open_file
+            .visit(format!("{prefix}half_open_file"), params);
+        self.open_file.visit(format!("{prefix}open_file"), params);
+        self.danger.visit(format!("{prefix}danger"), params);
+        self.max_danger_units
+            .visit(format!("{prefix}max_danger_units"), params);
+        self.danger_eg_divisor
+            .visit(format!("{prefix}danger_eg_divisor"), params);
+    }
+}
+
+/// Length of the `KingWeights::danger` table
+const DANGER_SIZE: usize = 64;
+/// The starting point of `KingWeights::danger`: quadratic up to a cap
+const DANGER: [i32; DANGER_SIZE] = {
+    let mut table = [0; DANGER_SIZE];
+    let mut i = 0;
+    while i < DANGER_SIZE {
+        let danger = (i * i / 5) as i32;
+        table[i] = if danger > 500 { 500 } else { danger };
+        i += 1;
+    }
+    table
+};
+
+impl Board {
+    /// How exposed the king of `color` is, as a penalty to `color`
+    pub fn king_safety(&self, w: &KingWeights, color: Color) -> S {
+        let us = self.side[color as usize];
+        let them = self.side[color.opposite() as usize];
+        let occupied = us | them;
+        let king = self.pieces[Piece::King as usize] & us;
+        let king_sq = king.as_idx().0;
+        let zone = BB(KING_TABLE[king_sq as usize]) | king;
+        let defended = self.attacks_by(color);
+
+        // Squares the enemy pieces would give check from
+        let checks = [
+            BB(0),
+            BB(KNIGHT_TABLE[king_sq as usize]),
+            get_bishop_moves(king, occupied),
+            get_rook_moves(king, occupied),
+            get_bishop_moves(king, occupied) | get_rook_moves(king, occupied),
+        ];
+        let safe = !(defended | them);
+
+        let mut attackers = 0;
+        let mut units = 0;
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            let mut can_check = false;
+            for sq in self.pieces[piece as usize] & them {
+                let attacks = self.get_attacks(piece, sq);
+                let hits = (attacks & zone).0.count_ones() as i32;
+                if hits > 0 {
+                    attackers += 1;
+                    units += w.attack_weight[piece as usize] * hits;
+                }
+                can_check |= !(attacks & checks[piece as usize] & safe).empty();
+            }
+            if can_check {
+                units += w.safe_check[piece as usize];
+            }
+        }
+        // A lone attacker can't do much
+        if attackers < 2 {
+            units = 0;
+        }
+
+        let pawns = self.pieces[Piece::Pawn as usize];
+        let ahead = forward_ranks(color, king_sq / 8);
+        let king_file = king_sq % 8;
+        for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+            let file = file_bb(file);
+            let ours = (pawns & us).0 & file;
+            let theirs = (pawns & them).0 & file;
+            let shield = nearest(color, ours & ahead);
+            units += w.shield[shield.map_or(3, |sq| rank_distance(sq, king_sq).min(3))];
+            if let Some(sq) = nearest(color, theirs & ahead) {
+                units += w.storm[rank_distance(sq, king_sq).min(4)];
+            }
+            if ours == 0 {
+                units += if theirs == 0 {
+                    w.open_file
+                } else {
+                    w.half_open_file
+                };
+            }
+        }
+
+        let max_units = w.max_danger_units.clamp(0, DANGER_SIZE as i32 - 1);
+        let danger = w.danger[units.clamp(0, max_units) as usize];
+        S(-danger, -danger / w.danger_eg_divisor.max(1))
+    }
+}
+
+/// The square of `pawns` closest to the own back rank of `color`
+fn nearest(color: Color, pawns: u64) -> Option<u8> {
+    if pawns == 0 {
+        return None;
+    }
+    Some(match color {
+        Color::White => pawns.trailing_zeros() as u8,
+        Color::Black => 63 - pawns.leading_zeros() as u8,
+    })
+}
+
+fn rank_distance(a: u8, b: u8) -> usize {
+    (a / 8).abs_diff(b / 8) as usize
+}
+
+#[test]
+fn test_king_safety() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let w = KingWeights::DEFAULT;
+    let mut b = Board::new();
+    load_fen(
+        &mut b,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    )
+    .unwrap();
+    assert_eq!(
+        b.king_safety(&w, Color::White),
+        b.king_safety(&w, Color::Black)
+    );
+
+    // Castled White king behind its pawns, Black king without a shield and a
+    // queen and a rook bearing down on it
+    load_fen(&mut b, "6k1/3R1p2/8/4Q3/8/8/5PPP/6K1 b - - 0 1").unwrap();
+    let white = b.king_safety(&w, Color::White);
+    let black = b.king_safety(&w, Color::Black);
+    assert_eq!(white, S(0, 0));
+    assert!(black.0 < -100);
+
+    // Pushing the shield pawns makes it worse
+    load_fen(&mut b, "6k1/3R1p2/8/4Q3/8/6PP/5P2/6K1 b - - 0 1").unwrap();
+    assert!(b.king_safety(&w, Color::White).0 < white.0);
+}
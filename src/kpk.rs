@@ -0,0 +1,222 @@
+//! King and pawn against king bitbase, built by retrograde analysis the first
+//! time it's needed. Positions are seen from the side with the pawn as White,
+//! with the pawn on the a to d files, the others are mirrored onto them.
+use std::sync::OnceLock;
+
+use crate::{core_types::Color, pawns::distance};
+
+/// Side to move, losing king, winning king, pawn on the 24 squares of files
+/// a to d and ranks 2 to 7
+const POSITIONS: usize = 2 * 64 * 64 * 24;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+fn index(stm: Color, wk: u8, wp: u8, bk: u8) -> usize {
+    let pawn = (wp % 8) as usize * 6 + (wp / 8 - 1) as usize;
+    stm as usize + 2 * (bk as usize + 64 * (wk as usize + 64 * pawn))
+}
+
+/// The squares a king on `sq` moves to
+fn king_moves(sq: u8) -> impl Iterator<Item = u8> {
+    let (file, rank) = ((sq % 8) as i8, (sq / 8) as i8);
+    (-1..=1)
+        .flat_map(move |df| (-1..=1).map(move |dr| (file + df, rank + dr)))
+        .filter(move |&(f, r)| (0..8).contains(&f) && (0..8).contains(&r) && (f, r) != (file, rank))
+        .map(|(f, r)| (r * 8 + f) as u8)
+}
+
+fn pawn_attacks(wp: u8, sq: u8) -> bool {
+    sq / 8 == wp / 8 + 1 && (sq % 8).abs_diff(wp % 8) == 1
+}
+
+/// Everything that is known without looking at the moves
+fn initial(stm: Color, wk: u8, wp: u8, bk: u8) -> Outcome {
+    let queening = wp + 8;
+    if distance(wk, bk) <= 1
+        || wk == wp
+        || bk == wp
+        || (stm == Color::White && pawn_attacks(wp, bk))
+    {
+        return Outcome::Invalid;
+    }
+    match stm {
+        // Promotes without the queen being taken
+        Color::White
+            if wp / 8 == 6
+                && wk != queening
+                && bk != queening
+                && (distance(bk, queening) > 1 || distance(wk, queening) == 1) =>
+        {
+            Outcome::Win
+        }
+        Color::Black => {
+            let takes_pawn = distance(bk, wp) == 1 && distance(wk, wp) > 1;
+            let stalemate = !pawn_attacks(wp, bk)
+                && king_moves(bk).all(|sq| distance(sq, wk) <= 1 || pawn_attacks(wp, sq));
+            if takes_pawn || stalemate {
+                Outcome::Draw
+            } else {
+                Outcome::Unknown
+            }
+        }
+        _ => Outcome::Unknown,
+    }
+}
+
+/// The outcome from the moves of the side to move, given the current
+/// outcomes of all the positions
+fn classify(table: &[Outcome], stm: Color, wk: u8, wp: u8, bk: u8) -> Outcome {
+    let (good, bad) = match stm {
+        Color::White => (Outcome::Win, Outcome::Draw),
+        Color::Black => (Outcome::Draw, Outcome::Win),
+    };
+    let (mut any_good, mut all_bad) = (false, true);
+    let mut result = |outcome: Outcome| {
+        any_good |= outcome == good;
+        all_bad &= outcome == bad;
+    };
+    match stm {
+        Color::White => {
+            for sq in king_moves(wk).filter(|&sq| sq != wp && distance(sq, bk) > 1) {
+                result(table[index(Color::Black, sq, wp, bk)]);
+            }
+            // Promotions are all in `initial`, as wins or by the queen being
+            // taken
+            let push = wp + 8;
+            if wp / 8 < 6 && push != wk && push != bk {
+                result(table[index(Color::Black, wk, push, bk)]);
+                let double = push + 8;
+                if wp / 8 == 1 && double != wk && double != bk {
+                    result(table[index(Color::Black, wk, double, bk)]);
+                }
+            }
+        }
+        Color::Black => {
+            // Taking the pawn is a draw that `initial` already found
+            for sq in king_moves(bk)
+                .filter(|&sq| sq != wp && distance(sq, wk) > 1 && !pawn_attacks(wp, sq))
+            {
+                result(table[index(Color::White, wk, wp, sq)]);
+            }
+        }
+    }
+    if any_good {
+        good
+    } else if all_bad {
+        // No moves at all left is mate, stalemates are in `initial`
+        bad
+    } else {
+        Outcome::Unknown
+    }
+}
+
+/// Every position, in index order
+fn positions() -> impl Iterator<Item = (Color, u8, u8, u8)> {
+    let pawns = (1..7).flat_map(|rank| (0..4).map(move |file| rank * 8 + file));
+    pawns.flat_map(|wp| {
+        (0..64).flat_map(move |wk| {
+            (0..64).flat_map(move |bk| [Color::White, Color::Black].map(|stm| (stm, wk, wp, bk)))
+        })
+    })
+}
+
+/// Goes over all the positions until nothing changes, then whatever is still
+/// unknown is a draw. One bit per position, set for wins.
+fn generate() -> Vec<u64> {
+    let mut table = vec![Outcome::Invalid; POSITIONS];
+    for (stm, wk, wp, bk) in positions() {
+        table[index(stm, wk, wp, bk)] = initial(stm, wk, wp, bk);
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (stm, wk, wp, bk) in positions() {
+            let i = index(stm, wk, wp, bk);
+            if table[i] == Outcome::Unknown {
+                table[i] = classify(&table, stm, wk, wp, bk);
+                changed |= table[i] != Outcome::Unknown;
+            }
+        }
+    }
+    let mut bits = vec![0; POSITIONS / 64];
+    for (i, outcome) in table.iter().enumerate() {
+        if *outcome == Outcome::Win {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+    bits
+}
+
+fn table() -> &'static [u64] {
+    static TABLE: OnceLock<Vec<u64>> = OnceLock::new();
+    TABLE.get_or_init(generate)
+}
+
+/// Builds the bitbase now rather than on the first probe
+pub fn init() {
+    table();
+}
+
+/// Whether White wins with its king on `wk` and pawn on `wp` against the
+/// king on `bk`, `stm` to move. The position has to be legal.
+pub fn probe(wk: u8, wp: u8, bk: u8, stm: Color) -> bool {
+    // Mirror the pawn onto the a to d files
+    let (wk, wp, bk) = if wp % 8 > 3 {
+        (wk ^ 7, wp ^ 7, bk ^ 7)
+    } else {
+        (wk, wp, bk)
+    };
+    let i = index(stm, wk, wp, bk);
+    table()[i / 64] & 1 << (i % 64) != 0
+}
+
+#[test]
+fn test_kpk() {
+    // Squares by name, the tests read better
+    let sq = |name: &str| {
+        let name = name.as_bytes();
+        (name[1] - b'1') * 8 + name[0] - b'a'
+    };
+    let probe = |wk, wp, bk, stm| probe(sq(wk), sq(wp), sq(bk), stm);
+
+    // A key square wins whoever moves
+    assert!(probe("e6", "e5", "e8", Color::White));
+    assert!(probe("e6", "e5", "e8", Color::Black));
+    assert!(probe("d6", "d4", "d8", Color::Black));
+    // Short of one, the opposition decides
+    assert!(!probe("e5", "e4", "e7", Color::White));
+    assert!(probe("e5", "e4", "e7", Color::Black));
+    // The pawn outruns the king unless it gets into its square
+    assert!(probe("a1", "h4", "c5", Color::White));
+    assert!(!probe("a1", "h4", "d5", Color::Black));
+    // The defending king in front of a rook pawn always draws
+    assert!(!probe("a6", "a5", "a8", Color::White));
+    assert!(!probe("b6", "h6", "h8", Color::White));
+    // Stalemate
+    assert!(!probe("f7", "g6", "h8", Color::Black));
+    // A knight pawn draws with the stalemate trick in the corner, b7+ Kb8
+    // leaves Kb6 stalemate and anything else drops the pawn. With Black to
+    // move Kb8 b7 Kc7 Ka7 wins.
+    assert!(!probe("a6", "b6", "a8", Color::White));
+    assert!(probe("a6", "b6", "a8", Color::Black));
+    // On the 7th the pawn queens if the king guards the queening square, and
+    // is lost if the defending king does
+    assert!(!probe("a1", "d7", "c7", Color::White));
+    assert!(!probe("a1", "d7", "c7", Color::Black));
+    assert!(probe("c7", "d7", "e7", Color::White));
+    assert!(probe("c7", "d7", "e7", Color::Black));
+    // From the 2nd rank the pawn can push two squares: the king has to get
+    // within 5 moves of h8, without the double push a4 would be close enough
+    assert!(probe("a1", "h2", "a4", Color::Black));
+    assert!(!probe("a1", "h2", "b4", Color::Black));
+    assert!(probe("a1", "h2", "b4", Color::White));
+
+    // Always the same table
+    assert_eq!(generate(), table());
+}
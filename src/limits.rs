@@ -0,0 +1,131 @@
+use crate::{board::Board, core_types::Color, moves::Move};
+
+/// Everything that can follow `go`
+#[derive(Clone, Default)]
+pub struct SearchLimits {
+    /// Remaining clock time in ms, indexed by color
+    pub time: [Option<u64>; 2],
+    /// Increment in ms, indexed by color
+    pub inc: [u64; 2],
+    pub movestogo: Option<u32>,
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    /// Search for a mate in this many moves
+    pub mate: Option<u32>,
+    pub movetime: Option<u64>,
+    pub infinite: bool,
+    pub ponder: bool,
+    /// Only these root moves are searched, all of them when empty
+    pub searchmoves: Vec<Move>,
+}
+
+const KEYWORDS: [&str; 12] = [
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+    "ponder",
+    "searchmoves",
+];
+
+impl SearchLimits {
+    /// Parses the arguments of `go`, `searchmoves` are matched against the moves of `b`
+    pub fn parse(args: &str, b: &Board) -> Self {
+        let mut limits = SearchLimits::default();
+        let mut words = args.split_whitespace().peekable();
+        while let Some(word) = words.next() {
+            // Some GUIs send negative times when the clock ran out, that is no
+            // time left
+            let mut number = || {
+                words
+                    .peek()
+                    .filter(|w| !KEYWORDS.contains(w))
+                    .and_then(|w| w.parse::<i64>().ok())
+                    .map(|n| {
+                        words.next();
+                        n.max(0) as u64
+                    })
+            };
+            match word {
+                "wtime" => limits.time[Color::White as usize] = number(),
+                "btime" => limits.time[Color::Black as usize] = number(),
+                "winc" => limits.inc[Color::White as usize] = number().unwrap_or(0),
+                "binc" => limits.inc[Color::Black as usize] = number().unwrap_or(0),
+                "movestogo" => limits.movestogo = number().map(|n| n as u32),
+                // Depth 0 wouldn't even come up with a move
+                "depth" => limits.depth = number().map(|n| n.clamp(1, u8::MAX as u64) as u8),
+                "nodes" => limits.nodes = number(),
+                "mate" => limits.mate = number().map(|n| n as u32),
+                "movetime" => limits.movetime = number(),
+                "infinite" => limits.infinite = true,
+                "ponder" => limits.ponder = true,
+                "searchmoves" => {
+                    let moves = b.gen_pseudo_legal();
+                    while let Some(text) = words.next_if(|w| !KEYWORDS.contains(w)) {
+                        let legal = moves.moves.iter().find(|m| {
+                            let mut new_b = *b;
+                            m.as_text() == text && new_b.make_move(m)
+                        });
+                        match legal {
+                            Some(m) => limits.searchmoves.push(*m),
+                            None => eprintln!("searchmoves: {text} is not a legal move"),
+                        }
+                    }
+                }
+                _ => eprintln!("Unknown go parameter: {word}"),
+            }
+        }
+        limits
+    }
+    /// True if only the GUI can end the search
+    pub fn unlimited(&self) -> bool {
+        self.infinite
+            || (self.time == [None, None]
+                && self.movetime.is_none()
+                && self.depth.is_none()
+                && self.nodes.is_none()
+                && self.mate.is_none())
+    }
+}
+
+#[test]
+fn test_parse_limits() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let mut b = Board::new();
+    load_fen(
+        &mut b,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    )
+    .unwrap();
+
+    let l = SearchLimits::parse("wtime 1000 btime -20 winc 10 movestogo 5", &b);
+    assert_eq!(l.time, [Some(1000), Some(0)]);
+    assert_eq!(l.inc, [10, 0]);
+    assert_eq!(l.movestogo, Some(5));
+    assert!(!l.unlimited());
+
+    let l = SearchLimits::parse("searchmoves e2e4 d2d4 e1e2 depth 3 infinite", &b);
+    let moves = l
+        .searchmoves
+        .iter()
+        .map(|m| m.as_text())
+        .collect::<Vec<_>>();
+    assert_eq!(moves, ["e2e4", "d2d4"]);
+    assert_eq!(l.depth, Some(3));
+    assert!(l.unlimited());
+
+    let l = SearchLimits::parse("", &b);
+    assert!(l.unlimited());
+    let l = SearchLimits::parse("depth 0", &b);
+    assert_eq!(l.depth, Some(1));
+    let l = SearchLimits::parse("nodes 5000", &b);
+    assert_eq!(l.nodes, Some(5000));
+    assert!(!l.unlimited());
+}
@@ -1,20 +1,63 @@
 mod attacks;
+mod bench;
 mod board;
 mod core_types;
+mod endgame;
 mod eval;
 mod fen;
+mod king_safety;
+mod kpk;
+mod limits;
+mod mate;
 mod movemake;
 mod moves;
+mod nnue;
+mod ordering;
+#[cfg(feature = "tune")]
+mod params;
+mod pawns;
+mod pieces;
 mod search;
+mod see;
+mod timeman;
+mod tt;
+mod tune;
 mod uci;
 mod utils;
+mod weights;
+mod zobrist;
+
+use std::env;
 
 use attacks::init_magics;
+use bench::{bench, DEFAULT_BENCH_DEPTH};
+use search::SearchOptions;
+use tune::tune;
 use uci::Game;
+use weights::Weights;
 
 fn main() {
     init_magics(false);
     eprintln!("finished init magics");
-    let mut g = Game { b: None };
+    kpk::init();
+    let args = env::args().collect::<Vec<_>>();
+    if args.get(1).is_some_and(|a| a == "bench") {
+        let depth = args.get(2).and_then(|d| d.parse().ok());
+        bench(
+            depth.unwrap_or(DEFAULT_BENCH_DEPTH),
+            SearchOptions::default(),
+            None,
+            &Weights::DEFAULT,
+        );
+        return;
+    }
+    if args.get(1).is_some_and(|a| a == "tune") {
+        match args.get(2) {
+            Some(data) => tune(data, args.get(3).map_or("tuned.txt", |o| o)),
+            None => eprintln!("usage: tune <positions file> [output file]"),
+        }
+        return;
+    }
+    let mut g = Game::new();
     g.uci_loop();
 }
@@ -0,0 +1,181 @@
+use std::{sync::atomic::Ordering, time::Instant};
+
+use crate::{
+    board::Board,
+    moves::Move,
+    search::{score_to_uci, Signals, MATE, MAX_PLY},
+};
+
+/// Proves or refutes a forced mate for the side to move, for `go mate`.
+///
+/// Unlike the normal search there is no evaluation and no pruning: the
+/// attacker needs one move that mates against every defence.
+pub struct MateSearch<'a> {
+    signals: &'a Signals,
+    /// Only consider checking moves for the attacker, much faster but it misses
+    /// mates with a quiet move in them
+    checks_only: bool,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    pv: [[Move; MAX_PLY]; MAX_PLY],
+    pv_len: [usize; MAX_PLY],
+}
+
+impl<'a> MateSearch<'a> {
+    pub fn new(signals: &'a Signals, checks_only: bool) -> Self {
+        MateSearch {
+            signals,
+            checks_only,
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            pv: [[Move::default(); MAX_PLY]; MAX_PLY],
+            pv_len: [0; MAX_PLY],
+        }
+    }
+
+    /// Looks for the shortest mate in at most `moves` moves and returns its line,
+    /// `None` if there is none or the GUI stopped the search first
+    pub fn search(&mut self, b: &Board, moves: u32) -> Option<Vec<Move>> {
+        let moves = moves.min(MAX_PLY as u32 / 2 - 1);
+        for n in 1..=moves {
+            let plies = 2 * n as usize - 1;
+            if self.attack(b, plies, 0) {
+                let pv = self.pv[0][..self.pv_len[0]].to_vec();
+                self.print_info(plies, MATE - plies as i32, &pv);
+                return Some(pv);
+            }
+            if self.stopped {
+                return None;
+            }
+            self.print_info(plies, 0, &[]);
+        }
+        None
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Attacker to move: can it mate within `plies`?
+    fn attack(&mut self, b: &Board, plies: usize, ply: usize) -> bool {
+        self.pv_len[ply] = ply;
+        if self.visit() {
+            return false;
+        }
+        // Checks first, they are the most likely to mate
+        let mut checks = Vec::new();
+        let mut quiets = Vec::new();
+        for m in b.gen_pseudo_legal() {
+            let mut new_b = *b;
+            if !new_b.make_move(&m) {
+                continue;
+            }
+            if new_b.in_check() {
+                checks.push((m, new_b));
+            } else if !self.checks_only && plies > 1 {
+                // A mate in one has to give check
+                quiets.push((m, new_b));
+            }
+        }
+        for (m, new_b) in checks.into_iter().chain(quiets) {
+            if self.defend(&new_b, plies - 1, ply + 1) {
+                self.update_pv(ply, m);
+                return true;
+            }
+            if self.stopped {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Defender to move: is every move mated within `plies`?
+    fn defend(&mut self, b: &Board, plies: usize, ply: usize) -> bool {
+        self.pv_len[ply] = ply;
+        if self.visit() {
+            return false;
+        }
+        let mut legal = false;
+        for m in b.gen_pseudo_legal() {
+            let mut new_b = *b;
+            if !new_b.make_move(&m) {
+                continue;
+            }
+            legal = true;
+            if plies == 0 || !self.attack(&new_b, plies - 1, ply + 1) {
+                return false;
+            }
+            // Every defence so far loses, show the last one tried
+            self.update_pv(ply, m);
+        }
+        // Every defence lost, or there was none: then it's mate unless stalemate
+        legal || b.in_check()
+    }
+
+    /// Counts the node, returns true once the search has to stop
+    fn visit(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(1024) && self.signals.stop.load(Ordering::Relaxed) {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    fn update_pv(&mut self, ply: usize, m: Move) {
+        self.pv[ply][ply] = m;
+        for i in ply + 1..self.pv_len[ply + 1] {
+            self.pv[ply][i] = self.pv[ply + 1][i];
+        }
+        self.pv_len[ply] = self.pv_len[ply + 1].max(ply + 1);
+    }
+
+    fn print_info(&self, plies: usize, score: i32, pv: &[Move]) {
+        let elapsed = self.start.elapsed();
+        let nps = (self.nodes as u128 * 1000) / elapsed.as_millis().max(1);
+        // Until a mate is proven there is nothing to say about the score
+        let score = if pv.is_empty() {
+            String::new()
+        } else {
+            format!(" score {}", score_to_uci(score))
+        };
+        let pv = pv
+            .iter()
+            .map(|m| format!(" {}", m.as_text()))
+            .collect::<String>();
+        let pv = if pv.is_empty() {
+            pv
+        } else {
+            format!(" pv{pv}")
+        };
+        println!(
+            "info depth {plies}{score} nodes {} nps {nps} time {}{pv}",
+            self.nodes,
+            elapsed.as_millis()
+        );
+    }
+}
+
+#[test]
+fn test_mate_search() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let signals = Signals::default();
+    let mut b = Board::new();
+    load_fen(&mut b, "1k6/2pp4/1p2p3/4q3/1P2B3/2PK1R2/8/8 w - - 0 46").unwrap();
+    assert!(MateSearch::new(&signals, false).search(&b, 1).is_none());
+    for checks_only in [false, true] {
+        let pv = MateSearch::new(&signals, checks_only)
+            .search(&b, 5)
+            .unwrap();
+        assert_eq!(pv.len(), 3);
+        assert_eq!(pv[0].as_text(), "f3f8");
+    }
+
+    // Mate in two with a quiet first move, which checks only can't see
+    load_fen(&mut b, "7k/8/5K2/8/8/8/8/6R1 w - - 0 1").unwrap();
+    let pv = MateSearch::new(&signals, false).search(&b, 2).unwrap();
+    assert_eq!(pv.len(), 3);
+    assert!(MateSearch::new(&signals, true).search(&b, 2).is_none());
+}
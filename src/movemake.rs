@@ -3,6 +3,7 @@ use core::panic;
 use crate::{
     board::Board,
     core_types::{Color, Piece, SquareIdx, BB},
+    eval::{Psqt, PsqtWeights},
     moves::{Move, MoveFlags},
 };
 
@@ -160,6 +161,29 @@ impl Board {
         self.color = self.color.opposite();
         true
     }
+    /// `make_move` that also keeps `psqt` up to date, which is left alone if the
+    /// move turns out to be illegal
+    pub fn make_move_psqt(&mut self, m: &Move, psqt: &mut Psqt, w: &PsqtWeights) -> bool {
+        let before = *self;
+        if !self.make_move(m) {
+            return false;
+        }
+        psqt.update(w, &before, self);
+        true
+    }
+    /// Passes the turn, used by null move pruning
+    pub fn make_null_move(&mut self) {
+        self.ep = SquareIdx(255);
+        self.color = self.color.opposite();
+    }
+    /// Knights, bishops, rooks and queens of the side to move
+    pub fn non_pawn_material(&self) -> BB {
+        let pieces = self.pieces[Piece::Knight as usize]
+            | self.pieces[Piece::Bishop as usize]
+            | self.pieces[Piece::Rook as usize]
+            | self.pieces[Piece::Queen as usize];
+        pieces & self.side[self.color as usize]
+    }
     pub fn make_move_list(&mut self, moves: &str) {
         if moves.is_empty() {
             return;
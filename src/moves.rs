@@ -82,6 +82,16 @@ impl Move {
         ((self.data >> 12 & 0b1111) as u8).into()
     }
 
+    pub fn is_promotion(&self) -> bool {
+        matches!(
+            self.flags(),
+            MoveFlags::PromoQueen
+                | MoveFlags::PromoRook
+                | MoveFlags::PromoKnight
+                | MoveFlags::PromoBishop
+        )
+    }
+
     pub fn as_text(&self) -> String {
         let mut ret = String::new();
         ret.push(((self.get_from_idx().0 % 8) + b'a') as char);
@@ -0,0 +1,338 @@
+//! HalfKP network evaluation: every non-king piece is a feature relative to
+//! the king of the side looking at the board, summed into an accumulator per
+//! side that moves update incrementally, then one clipped ReLU layer.
+//! Accumulator updates and the output layer use AVX2 when the CPU has it.
+//!
+//! Network files are little endian:
+//! - the magic `PRNN`, then `INPUTS` and `HIDDEN` as `u32`
+//! - feature weights as `i16`, `[INPUTS][HIDDEN]`
+//! - feature biases as `i16`, `[HIDDEN]`
+//! - output weights as `i16`, `[2 * HIDDEN]`, the side to move half first
+//! - the output bias as `i32`
+use std::fs;
+
+use crate::{
+    board::Board,
+    core_types::{Color, Piece},
+};
+
+/// King square, 10 pieces (pawn to queen of both colors), piece square
+pub const INPUTS: usize = 64 * 10 * 64;
+pub const HIDDEN: usize = 256;
+/// Quantization of the accumulator and of the output weights
+const QA: i32 = 255;
+const QB: i32 = 64;
+/// From network output to centipawns
+const SCALE: i32 = 400;
+const MAGIC: &[u8; 4] = b"PRNN";
+
+pub struct Network {
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("can't read {path}: {e}"))?;
+        Network::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let header = 4 + 4 + 4;
+        let expected = header + 2 * (INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN) + 4;
+        if bytes.len() != expected {
+            return Err(format!("expected {expected} bytes, got {}", bytes.len()));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+        if &bytes[..4] != MAGIC || u32_at(4) != INPUTS || u32_at(8) != HIDDEN {
+            return Err(format!(
+                "not a {INPUTS}x{HIDDEN} network, only HalfKP networks of that size are supported"
+            ));
+        }
+        let mut rest = &bytes[header..];
+        let mut i16s = |n: usize| {
+            let (taken, left) = rest.split_at(2 * n);
+            rest = left;
+            taken
+                .chunks_exact(2)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>()
+        };
+        let feature_weights = i16s(INPUTS * HIDDEN);
+        let feature_bias = i16s(HIDDEN);
+        let output_weights = i16s(2 * HIDDEN);
+        let output_bias = i32::from_le_bytes(rest.try_into().unwrap());
+        Ok(Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * HIDDEN..][..HIDDEN]
+    }
+
+    /// Evaluation from the point of view of `stm`, in centipawns
+    pub fn evaluate(&self, acc: &Accumulator, stm: Color) -> i32 {
+        let us = &acc.values[stm as usize];
+        let them = &acc.values[stm.opposite() as usize];
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // Safe, AVX2 is there
+            let sum = unsafe { output_avx2(us, them, &self.output_weights) };
+            return self.scale(sum);
+        }
+        self.scale(output_scalar(us, them, &self.output_weights))
+    }
+
+    /// From the output layer sum to centipawns, in 64 bits since large output
+    /// weights can take the sum out of `i32`
+    fn scale(&self, sum: i64) -> i32 {
+        let cp = (sum + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+        cp.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    }
+}
+
+fn output_scalar(us: &[i16; HIDDEN], them: &[i16; HIDDEN], weights: &[i16]) -> i64 {
+    let (ours, theirs) = weights.split_at(HIDDEN);
+    let half = |acc: &[i16; HIDDEN], weights: &[i16]| -> i64 {
+        acc.iter()
+            .zip(weights)
+            .map(|(&a, &w)| ((a as i32).clamp(0, QA) * w as i32) as i64)
+            .sum()
+    };
+    half(us, ours) + half(them, theirs)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn output_avx2(us: &[i16; HIDDEN], them: &[i16; HIDDEN], weights: &[i16]) -> i64 {
+    use std::arch::x86_64::*;
+    let zero = _mm256_setzero_si256();
+    let qa = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+    for (acc, weights) in [(us, &weights[..HIDDEN]), (them, &weights[HIDDEN..])] {
+        for i in (0..HIDDEN).step_by(16) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i) as *const __m256i);
+            let a = _mm256_min_epi16(_mm256_max_epi16(a, zero), qa);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            // Pairs of products summed into 32 bits, they don't fit in 16
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(a, w));
+        }
+    }
+    // Every lane holds 64 products at most, which fits, but not all of them
+    // together
+    let mut lanes = [0i32; 8];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+    lanes.iter().map(|&l| l as i64).sum()
+}
+
+/// Index of a `color` `piece` on `sq` seen by `perspective` with its king on
+/// `king`. Black sees the board flipped so both sides share the weights.
+fn feature(perspective: Color, king: usize, color: Color, piece: usize, sq: usize) -> usize {
+    let (king, sq) = match perspective {
+        Color::White => (king, sq),
+        Color::Black => (king ^ 56, sq ^ 56),
+    };
+    let piece = if color == perspective {
+        piece
+    } else {
+        piece + 5
+    };
+    (king * 10 + piece) * 64 + sq
+}
+
+fn king_square(b: &Board, color: Color) -> usize {
+    (b.pieces[Piece::King as usize] & b.side[color as usize])
+        .as_idx()
+        .0 as usize
+}
+
+/// The first layer for both perspectives, indexed by color
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+pub struct Accumulator {
+    values: [[i16; HIDDEN]; 2],
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Accumulator {
+            values: [[0; HIDDEN]; 2],
+        }
+    }
+}
+
+impl Accumulator {
+    /// Adds everything up from scratch
+    pub fn new(net: &Network, b: &Board) -> Self {
+        let mut acc = Accumulator::default();
+        for perspective in [Color::White, Color::Black] {
+            acc.refresh(net, b, perspective);
+        }
+        acc
+    }
+
+    fn refresh(&mut self, net: &Network, b: &Board, perspective: Color) {
+        let values = &mut self.values[perspective as usize];
+        values.copy_from_slice(&net.feature_bias);
+        let king = king_square(b, perspective);
+        for color in [Color::White, Color::Black] {
+            for piece in 0..Piece::King as usize {
+                for sq in b.pieces[piece] & b.side[color as usize] {
+                    let f = feature(perspective, king, color, piece, sq.as_idx().0 as usize);
+                    add(values, net.weights(f));
+                }
+            }
+        }
+    }
+
+    /// Applies the move that turned `before` into `after` like `Psqt::update`,
+    /// except that a king move changes every feature of its side so that side
+    /// starts over
+    pub fn update(&mut self, net: &Network, before: &Board, after: &Board) {
+        for perspective in [Color::White, Color::Black] {
+            let king = king_square(after, perspective);
+            if king != king_square(before, perspective) {
+                self.refresh(net, after, perspective);
+                continue;
+            }
+            let values = &mut self.values[perspective as usize];
+            for color in [Color::White, Color::Black] {
+                for piece in 0..Piece::King as usize {
+                    let old = before.pieces[piece] & before.side[color as usize];
+                    let new = after.pieces[piece] & after.side[color as usize];
+                    for sq in old & !new {
+                        let f = feature(perspective, king, color, piece, sq.as_idx().0 as usize);
+                        sub(values, net.weights(f));
+                    }
+                    for sq in new & !old {
+                        let f = feature(perspective, king, color, piece, sq.as_idx().0 as usize);
+                        add(values, net.weights(f));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn add(values: &mut [i16; HIDDEN], weights: &[i16]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safe, AVX2 is there
+        return unsafe { add_avx2(values, weights) };
+    }
+    for (v, w) in values.iter_mut().zip(weights) {
+        *v = v.wrapping_add(*w);
+    }
+}
+
+fn sub(values: &mut [i16; HIDDEN], weights: &[i16]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safe, AVX2 is there
+        return unsafe { sub_avx2(values, weights) };
+    }
+    for (v, w) in values.iter_mut().zip(weights) {
+        *v = v.wrapping_sub(*w);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn add_avx2(values: &mut [i16; HIDDEN], weights: &[i16]) {
+    use std::arch::x86_64::*;
+    let weights = &weights[..HIDDEN];
+    for i in (0..HIDDEN).step_by(16) {
+        let v = _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i);
+        let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+        let sum = _mm256_add_epi16(v, w);
+        _mm256_storeu_si256(values.as_mut_ptr().add(i) as *mut __m256i, sum);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn sub_avx2(values: &mut [i16; HIDDEN], weights: &[i16]) {
+    use std::arch::x86_64::*;
+    let weights = &weights[..HIDDEN];
+    for i in (0..HIDDEN).step_by(16) {
+        let v = _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i);
+        let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+        let diff = _mm256_sub_epi16(v, w);
+        _mm256_storeu_si256(values.as_mut_ptr().add(i) as *mut __m256i, diff);
+    }
+}
+
+/// A network file with small pseudo random weights
+#[cfg(test)]
+fn random_network_bytes() -> Vec<u8> {
+    let mut seed = 0x9e3779b97f4a7c15u64;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % 129) as i16 - 64
+    };
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend((INPUTS as u32).to_le_bytes());
+    bytes.extend((HIDDEN as u32).to_le_bytes());
+    for _ in 0..INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN {
+        bytes.extend(next().to_le_bytes());
+    }
+    bytes.extend(1234i32.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn test_nnue() {
+    let bytes = random_network_bytes();
+    assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    let net = Network::from_bytes(&bytes).unwrap();
+
+    // The AVX2 updates wrap around like the plain loops
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        let (mut simd, mut scalar) = ([i16::MAX - 20; HIDDEN], [i16::MAX - 20; HIDDEN]);
+        // Safe, AVX2 is there
+        unsafe {
+            add_avx2(&mut simd, net.weights(7));
+            sub_avx2(&mut simd, net.weights(9000));
+        }
+        for ((v, a), s) in scalar.iter_mut().zip(net.weights(7)).zip(net.weights(9000)) {
+            *v = v.wrapping_add(*a).wrapping_sub(*s);
+        }
+        assert_eq!(simd, scalar);
+    }
+
+    // Saturated accumulators and the largest output weights don't fit in i32
+    let mut big = Network::from_bytes(&bytes).unwrap();
+    big.output_weights.fill(i16::MAX);
+    let acc = Accumulator {
+        values: [[QA as i16; HIDDEN]; 2],
+    };
+    let sum = 2 * HIDDEN as i64 * QA as i64 * i16::MAX as i64;
+    let expected = (sum + 1234) * SCALE as i64 / (QA * QB) as i64;
+    assert_eq!(big.evaluate(&acc, Color::White) as i64, expected);
+
+    let check = |b: &Board, acc: &Accumulator| {
+        assert_eq!(acc.values, Accumulator::new(&net, b).values);
+        let scalar = output_scalar(&acc.values[0], &acc.values[1], &net.output_weights);
+        assert_eq!(net.evaluate(acc, Color::White), net.scale(scalar));
+    };
+    let start = |b: &Board| {
+        let acc = Accumulator::new(&net, b);
+        check(b, &acc);
+        acc
+    };
+    crate::utils::walk_moves(2, start, &mut |before, after, acc| {
+        let mut acc = acc;
+        acc.update(&net, before, after);
+        check(after, &acc);
+        acc
+    });
+}
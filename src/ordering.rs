@@ -0,0 +1,178 @@
+use crate::{
+    board::Board,
+    core_types::Piece,
+    moves::{Move, MoveList},
+    search::{SearchParams, MAX_PLY},
+    see::SEE_VALUES,
+};
+
+const GOOD_CAPTURE: i32 = 2_000_000;
+const BAD_CAPTURE: i32 = -2_000_000;
+
+/// A move made earlier in the line, with the piece that made it
+pub type PlayedMove = Option<(Move, Piece)>;
+
+/// Quiet move statistics, kept between searches and aged at the start of each one
+pub struct History {
+    killers: [[Move; 2]; MAX_PLY],
+    /// `[color][from][to]`
+    butterfly: [[[i32; 64]; 64]; 2],
+    /// Indexed by the `[from][to]` of the previous move
+    countermoves: [[Move; 64]; 64],
+    /// `[previous piece][previous to][piece][to]`
+    continuation: Box<[[[[i32; 64]; 6]; 64]; 6]>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            killers: [[Move::default(); 2]; MAX_PLY],
+            butterfly: [[[0; 64]; 64]; 2],
+            countermoves: [[Move::default(); 64]; 64],
+            // Straight on the heap, it's too big for the stack of a search
+            // thread
+            continuation: vec![[[[0; 64]; 6]; 64]; 6]
+                .into_boxed_slice()
+                .try_into()
+                .unwrap(),
+        }
+    }
+
+    /// Everything back to how `new` leaves it, in place
+    pub fn clear(&mut self) {
+        self.killers = [[Move::default(); 2]; MAX_PLY];
+        self.butterfly = [[[0; 64]; 64]; 2];
+        self.countermoves = [[Move::default(); 64]; 64];
+        self.continuation
+            .iter_mut()
+            .flatten()
+            .for_each(|h| h.fill([0; 64]));
+    }
+
+    /// Between searches: killers are position specific, history just fades
+    pub fn age(&mut self) {
+        self.killers = [[Move::default(); 2]; MAX_PLY];
+        for h in self.butterfly.iter_mut().flatten().flatten() {
+            *h /= 2;
+        }
+        for h in self.continuation.iter_mut().flatten().flatten().flatten() {
+            *h /= 2;
+        }
+    }
+
+    fn quiet_score(&self, b: &Board, m: &Move, piece: Piece, prev: PlayedMove) -> i32 {
+        let from = m.get_from_idx().0 as usize;
+        let to = m.get_to_idx().0 as usize;
+        let mut score = self.butterfly[b.color as usize][from][to];
+        if let Some((prev, prev_piece)) = prev {
+            let prev_to = prev.get_to_idx().0 as usize;
+            score += self.continuation[prev_piece as usize][prev_to][piece as usize][to];
+        }
+        score
+    }
+
+    /// Scores and sorts `moves`, best first
+    pub fn order(
+        &self,
+        b: &Board,
+        moves: MoveList,
+        tt_move: Move,
+        ply: usize,
+        prev: PlayedMove,
+        p: &SearchParams,
+    ) -> Vec<(Move, Piece)> {
+        let counter = prev.map(|(p, _)| {
+            self.countermoves[p.get_from_idx().0 as usize][p.get_to_idx().0 as usize]
+        });
+        let mut scored = moves
+            .into_iter()
+            .map(|m| {
+                let piece = b.piece_on(m.get_from()).unwrap_or(Piece::Pawn);
+                let score = if m == tt_move {
+                    p.order_tt_move
+                } else if b.is_tactical(&m) {
+                    let base = if b.see(&m) >= 0 {
+                        GOOD_CAPTURE
+                    } else {
+                        BAD_CAPTURE
+                    };
+                    base + mvv_lva(b, &m)
+                } else if m == self.killers[ply][0] {
+                    p.order_killer
+                } else if m == self.killers[ply][1] {
+                    p.order_killer - 1
+                } else if Some(m) == counter {
+                    p.order_countermove
+                } else {
+                    self.quiet_score(b, &m, piece, prev)
+                };
+                (m, piece, score)
+            })
+            .collect::<Vec<_>>();
+        scored.sort_by_key(|(_, _, score)| -score);
+        scored.into_iter().map(|(m, p, _)| (m, p)).collect()
+    }
+
+    /// Rewards the quiet move that caused a beta cutoff and punishes the quiets
+    /// tried before it
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        b: &Board,
+        best: (Move, Piece),
+        tried: &[(Move, Piece)],
+        ply: usize,
+        prev: PlayedMove,
+        depth: u8,
+        p: &SearchParams,
+    ) {
+        let bonus = (p.history_bonus * depth as i32 * depth as i32).min(p.history_max);
+        let (m, _) = best;
+        let limit = p.history_limit.max(1);
+        if self.killers[ply][0] != m {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = m;
+        }
+        if let Some((p, _)) = prev {
+            self.countermoves[p.get_from_idx().0 as usize][p.get_to_idx().0 as usize] = m;
+        }
+        self.add(b, best, prev, bonus, limit);
+        for quiet in tried.iter().filter(|(q, _)| *q != m) {
+            self.add(b, *quiet, prev, -bonus, limit);
+        }
+    }
+
+    fn add(
+        &mut self,
+        b: &Board,
+        (m, piece): (Move, Piece),
+        prev: PlayedMove,
+        bonus: i32,
+        limit: i32,
+    ) {
+        // Gravity keeps the values within +-limit
+        let apply = |h: &mut i32, bonus: i32| *h += bonus - *h * bonus.abs() / limit;
+        let from = m.get_from_idx().0 as usize;
+        let to = m.get_to_idx().0 as usize;
+        apply(&mut self.butterfly[b.color as usize][from][to], bonus);
+        if let Some((prev, prev_piece)) = prev {
+            let prev_to = prev.get_to_idx().0 as usize;
+            apply(
+                &mut self.continuation[prev_piece as usize][prev_to][piece as usize][to],
+                bonus,
+            );
+        }
+    }
+}
+
+/// Most valuable victim first, least valuable attacker breaking ties
+pub fn mvv_lva(b: &Board, m: &Move) -> i32 {
+    let victim = b.captured_piece(m).map_or(0, |p| SEE_VALUES[p as usize]);
+    let attacker = b.piece_on(m.get_from()).map_or(0, |p| p as i32);
+    let promo = if m.is_promotion() {
+        SEE_VALUES[Piece::Queen as usize]
+    } else {
+        0
+    };
+    (victim + promo) * 8 - attacker
+}
@@ -0,0 +1,116 @@
+//! Every tunable number of the engine by name, settable through `setoption`
+//! so SPSA can tune them without recompiling. Only built with the `tune`
+//! feature.
+use crate::{search::SearchParams, weights::Weights};
+
+/// SPSA learning rate at the end of the tuning run, the same for everything
+const R_END: f64 = 0.002;
+
+/// A parameter with the range SPSA may move it in and its step size
+struct Entry<'a> {
+    name: String,
+    value: &'a mut i32,
+    default: i32,
+    min: i32,
+    max: i32,
+    step: f64,
+}
+
+/// All the parameters of `search` and `eval`, ranges and steps are around the
+/// defaults so they don't move while tuning
+fn registry<'a>(search: &'a mut SearchParams, eval: &'a mut Weights) -> Vec<Entry<'a>> {
+    let mut defaults = SearchParams::DEFAULT;
+    let defaults = defaults.params().map(|(_, d, ..)| *d);
+    let mut entries = search
+        .params()
+        .into_iter()
+        .zip(defaults)
+        .map(|((name, value, min, max, step), default)| Entry {
+            name: name.to_owned(),
+            value,
+            default,
+            min,
+            max,
+            step,
+        })
+        .collect::<Vec<_>>();
+
+    let mut defaults = Weights::DEFAULT;
+    let defaults = defaults.params().into_iter().map(|(_, d)| *d);
+    for ((name, value), default) in eval.params().into_iter().zip(defaults) {
+        // Eval weights have no natural range, let them go half their size
+        // either way, more for the small ones
+        let span = (default.abs() / 2).max(10);
+        entries.push(Entry {
+            name,
+            value,
+            default,
+            min: default - span,
+            max: default + span,
+            step: (span as f64 / 5.0).max(1.0),
+        });
+    }
+    entries
+}
+
+/// The UCI option of every parameter, for `uci`
+pub fn print_options() {
+    let (mut search, mut eval) = (SearchParams::DEFAULT, Weights::DEFAULT);
+    for e in registry(&mut search, &mut eval) {
+        println!(
+            "option name {} type spin default {} min {} max {}",
+            e.name, e.default, e.min, e.max
+        );
+    }
+}
+
+/// One `name, int, value, min, max, step, learning rate` line per parameter,
+/// the input format of SPSA tuners
+pub fn print_spsa(params: &SearchParams, weights: &Weights) {
+    let (mut search, mut eval) = (*params, *weights);
+    for e in registry(&mut search, &mut eval) {
+        println!(
+            "{}, int, {}, {}, {}, {}, {R_END}",
+            e.name, e.value, e.min, e.max, e.step
+        );
+    }
+}
+
+/// Sets the parameter `name`, any case, in `params` or `weights`, returns
+/// false if there is none
+pub fn set_param(
+    name: &str,
+    value: &str,
+    params: &mut SearchParams,
+    weights: &mut Weights,
+) -> bool {
+    let mut entries = registry(params, weights);
+    let Some(entry) = entries.iter_mut().find(|e| e.name.to_lowercase() == name) else {
+        return false;
+    };
+    match value.parse::<i32>() {
+        Ok(v) => *entry.value = v,
+        Err(_) => eprintln!("Invalid {} value: {value}", entry.name),
+    }
+    true
+}
+
+#[test]
+fn test_registry() {
+    let (mut search, mut eval) = (SearchParams::DEFAULT, Weights::DEFAULT);
+    let entries = registry(&mut search, &mut eval);
+    assert!(entries
+        .iter()
+        .all(|e| e.min <= *e.value && *e.value <= e.max));
+    assert!(entries.iter().all(|e| e.step >= 0.5));
+    let mut names = entries
+        .iter()
+        .map(|e| e.name.to_lowercase())
+        .collect::<Vec<_>>();
+    let count = names.len();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), count);
+    assert!(names.contains(&"rfpmargin".to_owned()));
+    assert!(names.contains(&"pieces.bishop_pair.mg".to_owned()));
+}
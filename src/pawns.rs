@@ -0,0 +1,260 @@
+use crate::{
+    attacks::{pawn_attacks_bb, WALL_LEFT, WALL_RIGHT},
+    board::Board,
+    core_types::{Color, Piece, BB},
+    eval::S,
+    weights::Param,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct PawnWeights {
+    pub doubled: S,
+    pub isolated: S,
+    pub backward: S,
+    /// Defended or side by side with another pawn, by relative rank
+    pub connected: [S; 8],
+    /// By relative rank
+    pub passed: [S; 8],
+    /// Endgame bonus per rank of advancement when nothing stands on the way
+    /// to promotion
+    pub free_path: i32,
+    /// Endgame bonus per rank of advancement for every square the enemy king
+    /// is away from the square in front of the pawn, and penalty for our own
+    /// king
+    pub their_king_distance: i32,
+    pub our_king_distance: i32,
+}
+
+impl PawnWeights {
+    pub const DEFAULT: PawnWeights = PawnWeights {
+        doubled: S(-10, -25),
+        isolated: S(-6, -14),
+        backward: S(-8, -10),
+        connected: [
+            S(0, 0),
+            S(6, 2),
+            S(8, 4),
+            S(12, 8),
+            S(25, 18),
+            S(45, 35),
+            S(80, 60),
+            S(0, 0),
+        ],
+        passed: [
+            S(0, 0),
+            S(3, 10),
+            S(7, 15),
+            S(10, 25),
+            S(25, 50),
+            S(50, 90),
+            S(90, 150),
+            S(0, 0),
+        ],
+        free_path: 8,
+        their_king_distance: 5,
+        our_king_distance: 2,
+    };
+
+    pub fn params<'a>(&'a mut self, prefix: &str, params: &mut Vec<(String, &'a mut i32)>) {
+        self.doubled.visit(format!("{prefix}doubled"), params);
+        self.isolated.visit(format!("{prefix}isolated"), params);
+        self.backward.visit(format!("{prefix}backward"), params);
+        self.connected.visit(format!("{prefix}connected"), params);
+        self.passed.visit(format!("{prefix}passed"), params);
+        self.free_path.visit(format!("{prefix}free_path"), params);
+        self.their_king_distance
+            .visit(format!("{prefix}their_king_distance"), params);
+        self.our_king_distance
+            .visit(format!("{prefix}our_king_distance"), params);
+    }
+}
+
+/// Number of entries, a power of two
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+pub fn file_bb(file: u8) -> u64 {
+    WALL_LEFT << file
+}
+
+pub fn adjacent_files(file: u8) -> u64 {
+    let f = file_bb(file);
+    (f << 1 & !WALL_LEFT) | (f >> 1 & !WALL_RIGHT)
+}
+
+/// The ranks in front of `rank`, seen from `color`
+pub fn forward_ranks(color: Color, rank: u8) -> u64 {
+    match color {
+        Color::White if rank == 7 => 0,
+        Color::White => u64::MAX << (8 * (rank + 1)),
+        Color::Black => !(u64::MAX << (8 * rank)),
+    }
+}
+
+pub fn relative_rank(color: Color, sq: u8) -> u8 {
+    match color {
+        Color::White => sq / 8,
+        Color::Black => 7 - sq / 8,
+    }
+}
+
+/// The square right in front of `sq`
+pub fn push(color: Color, sq: u8) -> u8 {
+    match color {
+        Color::White => sq + 8,
+        Color::Black => sq - 8,
+    }
+}
+
+pub fn distance(a: u8, b: u8) -> i32 {
+    let files = (a % 8).abs_diff(b % 8);
+    let ranks = (a / 8).abs_diff(b / 8);
+    files.max(ranks) as i32
+}
+
+/// Everything about the pawns that only depends on the pawns
+#[derive(Clone, Copy, Default)]
+pub struct PawnEntry {
+    key: u64,
+    /// From White's side
+    pub score: S,
+    /// Indexed by color
+    pub passed: [BB; 2],
+}
+
+impl PawnEntry {
+    fn new(b: &Board, w: &PawnWeights, key: u64) -> Self {
+        let mut entry = PawnEntry {
+            key,
+            ..Default::default()
+        };
+        for color in [Color::White, Color::Black] {
+            let (score, passed) = pawn_structure(b, w, color);
+            entry.passed[color as usize] = passed;
+            match color {
+                Color::White => entry.score += score,
+                Color::Black => entry.score -= score,
+            }
+        }
+        entry
+    }
+}
+
+/// Pawn structure score of `color` and its passed pawns
+pub fn pawn_structure(b: &Board, w: &PawnWeights, color: Color) -> (S, BB) {
+    let pawns = b.pieces[Piece::Pawn as usize];
+    let ours = (pawns & b.side[color as usize]).0;
+    let theirs = (pawns & b.side[color.opposite() as usize]).0;
+    let defended = pawn_attacks_bb(color, BB(ours)).0;
+    let their_attacks = pawn_attacks_bb(color.opposite(), BB(theirs)).0;
+
+    let mut score = S(0, 0);
+    let mut passed = BB(0);
+    for pawn in BB(ours) {
+        let sq = pawn.as_idx().0;
+        let (file, rank) = (sq % 8, sq / 8);
+        let relative = relative_rank(color, sq);
+        let ahead = forward_ranks(color, rank);
+        let adjacent = adjacent_files(file);
+
+        let doubled = ours & file_bb(file) & ahead != 0;
+        if doubled {
+            score += w.doubled;
+        }
+        if ours & adjacent == 0 {
+            score += w.isolated;
+        } else if ours & adjacent & !ahead == 0 && their_attacks & (1 << push(color, sq)) != 0 {
+            // Nothing can come up to support it and it can't safely advance
+            score += w.backward;
+        }
+        let phalanx = ours & adjacent & (0xff << (8 * rank)) != 0;
+        if phalanx || defended & pawn.0 != 0 {
+            score += w.connected[relative as usize];
+        }
+        if !doubled && theirs & (file_bb(file) | adjacent) & ahead == 0 {
+            score += w.passed[relative as usize];
+            passed |= pawn;
+        }
+    }
+    (score, passed)
+}
+
+/// Caches pawn structure evaluations by the pawn key, one per search thread
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        PawnTable {
+            entries: vec![PawnEntry::default(); PAWN_TABLE_SIZE],
+        }
+    }
+
+    /// The entry of `b`, whose pawn key is `key`. The table has to be used
+    /// with the same weights all along.
+    pub fn probe(&mut self, b: &Board, w: &PawnWeights, key: u64) -> PawnEntry {
+        let entry = &mut self.entries[key as usize & (PAWN_TABLE_SIZE - 1)];
+        // The empty key belongs to positions without pawns, which are cheap anyway
+        if entry.key != key || key == 0 {
+            *entry = PawnEntry::new(b, w, key);
+        }
+        *entry
+    }
+}
+
+impl Board {
+    /// Passed pawn terms that depend on more than the pawns, so they can't be
+    /// cached: how far the kings are and whether the way to promotion is free.
+    /// For the `passed` pawns of `color`, endgame only.
+    pub fn passed_pawn_race(&self, w: &PawnWeights, color: Color, passed: BB) -> S {
+        let occupied = (self.side[0] | self.side[1]).0;
+        let kings = self.pieces[Piece::King as usize];
+        let our_king = (kings & self.side[color as usize]).as_idx().0;
+        let their_king = (kings & self.side[color.opposite() as usize]).as_idx().0;
+        let mut bonus = 0;
+        for pawn in passed {
+            let sq = pawn.as_idx().0;
+            let relative = relative_rank(color, sq) as i32;
+            if relative < 3 {
+                continue;
+            }
+            let weight = relative - 2;
+            let stop = push(color, sq);
+            bonus += weight
+                * (distance(their_king, stop).min(5) * w.their_king_distance
+                    - distance(our_king, stop).min(5) * w.our_king_distance);
+            let path = file_bb(sq % 8) & forward_ranks(color, sq / 8);
+            if occupied & path == 0 {
+                bonus += weight * w.free_path;
+            }
+        }
+        S(0, bonus)
+    }
+}
+
+#[test]
+fn test_pawn_structure() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let mut b = Board::new();
+    // White: doubled isolated c pawns, the front one passed, and a passed a
+    // pawn. Black: a passed chain
+    load_fen(&mut b, "4k3/8/8/P7/2P2p2/2P1p3/3p4/4K3 w - - 0 1").unwrap();
+    let w = PawnWeights::DEFAULT;
+    let (white, passed) = pawn_structure(&b, &w, Color::White);
+    assert_eq!(passed.0, 1 << 32 | 1 << 26);
+    assert_eq!(
+        white,
+        w.doubled + w.isolated * 3 + w.passed[3] + w.passed[4]
+    );
+    let (_, passed) = pawn_structure(&b, &w, Color::Black);
+    assert_eq!(passed.0.count_ones(), 3);
+
+    // Same pawns, pieces moved around: same key, same cached entry
+    let mut table = PawnTable::new();
+    let entry = table.probe(&b, &w, b.pawn_hash());
+    load_fen(&mut b, "3k4/8/8/P7/2P2p2/2P1p3/3p4/K7 b - - 0 1").unwrap();
+    assert_eq!(table.probe(&b, &w, b.pawn_hash()).score, entry.score);
+    load_fen(&mut b, "3k4/8/8/P7/2P2p2/2P1p3/3p4/K6P b - - 0 1").unwrap();
+    assert_ne!(b.pawn_hash(), entry.key);
+}
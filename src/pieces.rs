@@ -0,0 +1,208 @@
+use crate::{
+    attacks::pawn_attacks_bb,
+    board::Board,
+    core_types::{Color, Piece, BB},
+    eval::S,
+    pawns::{adjacent_files, file_bb, forward_ranks, relative_rank},
+    weights::Param,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct PieceWeights {
+    /// Per safe square a piece can go to, by piece
+    pub mobility: [S; 6],
+    /// Number of safe squares a piece usually has, fewer is a penalty and
+    /// more a bonus
+    pub mobility_base: [i32; 6],
+    /// Knight and bishop on a square enemy pawns can never chase it from,
+    /// defended by a pawn
+    pub outpost: [S; 6],
+    pub bishop_pair: S,
+    pub rook_open_file: S,
+    pub rook_semi_open_file: S,
+    /// Rook on the seventh rank with the enemy king behind it or pawns to eat
+    pub rook_seventh: S,
+    /// Rook with barely any moves, shut in by its own king that can't castle
+    pub trapped_rook: S,
+    /// Bishop that took the a7 or h7 pawn and got cut off by the b6 or g6 pawn
+    pub trapped_bishop: S,
+}
+
+impl PieceWeights {
+    pub const DEFAULT: PieceWeights = PieceWeights {
+        mobility: [S(0, 0), S(4, 4), S(5, 5), S(2, 4), S(1, 2), S(0, 0)],
+        mobility_base: [0, 4, 6, 7, 13, 0],
+        outpost: [S(0, 0), S(25, 15), S(12, 6), S(0, 0), S(0, 0), S(0, 0)],
+        bishop_pair: S(30, 50),
+        rook_open_file: S(25, 10),
+        rook_semi_open_file: S(12, 8),
+        rook_seventh: S(10, 25),
+        trapped_rook: S(-40, -10),
+        trapped_bishop: S(-80, -80),
+    };
+
+    pub fn params<'a>(&'a mut self, prefix: &str, params: &mut Vec<(String, &'a mut i32)>) {
+        self.mobility.visit(format!("{prefix}mobility"), params);
+        self.mobility_base
+            .visit(format!("{prefix}mobility_base"), params);
+        self.outpost.visit(format!("{prefix}outpost"), params);
+        self.bishop_pair
+            .visit(format!("{prefix}bishop_pair"), params);
+        self.rook_open_file
+            .visit(format!("{prefix}rook_open_file"), params);
+        self.rook_semi_open_file
+            .visit(format!("{prefix}rook_semi_open_file"), params);
+        self.rook_seventh
+            .visit(format!("{prefix}rook_seventh"), params);
+        self.trapped_rook
+            .visit(format!("{prefix}trapped_rook"), params);
+        self.trapped_bishop
+            .visit(format!("{prefix}trapped_bishop"), params);
+    }
+}
+
+/// The piece activity terms of one color
+#[derive(Clone, Copy, Default)]
+pub struct Activity {
+    pub mobility: S,
+    pub outposts: S,
+    pub bishop_pair: S,
+    pub rooks: S,
+    pub trapped: S,
+}
+
+impl Activity {
+    pub fn total(&self) -> S {
+        self.mobility + self.outposts + self.bishop_pair + self.rooks + self.trapped
+    }
+}
+
+impl Board {
+    /// How well placed the knights, bishops, rooks and queens of `color` are
+    pub fn activity(&self, w: &PieceWeights, color: Color) -> Activity {
+        let us = self.side[color as usize];
+        let them = self.side[color.opposite() as usize];
+        let pawns = self.pieces[Piece::Pawn as usize];
+        let our_pawns = pawns & us;
+        let their_pawns = pawns & them;
+        let defended_by_pawns = pawn_attacks_bb(color, our_pawns);
+        // Squares worth counting: not blocked by our own pieces and not
+        // attacked by enemy pawns
+        let safe = !(us | pawn_attacks_bb(color.opposite(), their_pawns));
+
+        let mut activity = Activity::default();
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for sq in self.pieces[piece as usize] & us {
+                let idx = sq.as_idx().0;
+                let moves = (self.get_attacks(piece, sq) & safe).0.count_ones() as i32;
+                activity.mobility +=
+                    w.mobility[piece as usize] * (moves - w.mobility_base[piece as usize]);
+
+                match piece {
+                    Piece::Knight | Piece::Bishop => {
+                        let rank = relative_rank(color, idx);
+                        let chasers = adjacent_files(idx % 8) & forward_ranks(color, idx / 8);
+                        if (3..=5).contains(&rank)
+                            && !(defended_by_pawns & sq).empty()
+                            && (their_pawns & BB(chasers)).empty()
+                        {
+                            activity.outposts += w.outpost[piece as usize];
+                        }
+                        if piece == Piece::Bishop && self.bishop_trapped(color, idx) {
+                            activity.trapped += w.trapped_bishop;
+                        }
+                    }
+                    Piece::Rook => {
+                        let file = BB(file_bb(idx % 8));
+                        if (pawns & file).empty() {
+                            activity.rooks += w.rook_open_file;
+                        } else if (our_pawns & file).empty() {
+                            activity.rooks += w.rook_semi_open_file;
+                        }
+                        if relative_rank(color, idx) == 6 && self.seventh_rank_targets(color) {
+                            activity.rooks += w.rook_seventh;
+                        }
+                        if moves <= 3 && self.rook_trapped(color, idx) {
+                            activity.trapped += w.trapped_rook;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        if (self.pieces[Piece::Bishop as usize] & us).0.count_ones() >= 2 {
+            activity.bishop_pair = w.bishop_pair;
+        }
+        activity
+    }
+
+    /// Is the enemy king stuck on its back rank or are there enemy pawns on
+    /// the seventh rank, from the point of view of `color`
+    fn seventh_rank_targets(&self, color: Color) -> bool {
+        let them = self.side[color.opposite() as usize];
+        let (seventh, eighth) = match color {
+            Color::White => (BB(0xff << 48), BB(0xff << 56)),
+            Color::Black => (BB(0xff << 8), BB(0xff)),
+        };
+        !(self.pieces[Piece::Pawn as usize] & them & seventh).empty()
+            || !(self.pieces[Piece::King as usize] & them & eighth).empty()
+    }
+
+    /// Rook in the corner with the uncastled king next to it on the same side
+    fn rook_trapped(&self, color: Color, rook: u8) -> bool {
+        let king = (self.pieces[Piece::King as usize] & self.side[color as usize])
+            .as_idx()
+            .0;
+        if relative_rank(color, king) != 0 || relative_rank(color, rook) != 0 {
+            return false;
+        }
+        let (king_file, rook_file) = (king % 8, rook % 8);
+        (king_file >= 5 && rook_file > king_file) || (king_file <= 2 && rook_file < king_file)
+    }
+
+    /// Bishop on a7 or h7 (a2 or h2 for Black) with an enemy pawn on b6 or
+    /// g6 blocking its way out
+    fn bishop_trapped(&self, color: Color, bishop: u8) -> bool {
+        let their_pawns = self.pieces[Piece::Pawn as usize] & self.side[color.opposite() as usize];
+        let blocker = match (color, bishop) {
+            (Color::White, 48) => 41,
+            (Color::White, 55) => 46,
+            (Color::Black, 8) => 17,
+            (Color::Black, 15) => 22,
+            _ => return false,
+        };
+        !(their_pawns & BB(1 << blocker)).empty()
+    }
+}
+
+#[test]
+fn test_activity() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let w = PieceWeights::DEFAULT;
+    let mut b = Board::new();
+    load_fen(
+        &mut b,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    )
+    .unwrap();
+    let white = b.activity(&w, Color::White);
+    assert_eq!(white.total(), b.activity(&w, Color::Black).total());
+    assert_eq!(white.bishop_pair, w.bishop_pair);
+    assert_eq!(white.trapped, S(0, 0));
+
+    // Knight outpost on d5, rooks on the open e file, and a Black bishop
+    // trapped on a2
+    load_fen(&mut b, "4r1k1/pp3ppp/3p4/3N4/2P5/1P6/b4PPP/4R1K1 w - - 0 1").unwrap();
+    let white = b.activity(&w, Color::White);
+    let black = b.activity(&w, Color::Black);
+    assert_eq!(white.outposts, w.outpost[Piece::Knight as usize]);
+    assert_eq!(white.rooks, w.rook_open_file);
+    assert_eq!(black.rooks, w.rook_open_file);
+    assert_eq!(black.trapped, w.trapped_bishop);
+    assert_eq!(black.bishop_pair, S(0, 0));
+
+    // Uncastled king shutting the h1 rook in
+    load_fen(&mut b, "4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1").unwrap();
+    assert_eq!(b.activity(&w, Color::White).trapped, w.trapped_rook);
+}
@@ -0,0 +1,1477 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::Board,
+    core_types::{Color, Piece},
+    eval::Psqt,
+    limits::SearchLimits,
+    mate::MateSearch,
+    moves::Move,
+    nnue::{Accumulator, Network},
+    ordering::{mvv_lva, History, PlayedMove},
+    pawns::PawnTable,
+    see::SEE_VALUES,
+    timeman::{TimeManager, DEFAULT_MOVE_OVERHEAD},
+    tt::{score_from_tt, score_to_tt, Bound, TranspositionTable},
+    weights::Weights,
+    zobrist::Keys,
+};
+
+pub const MAX_PLY: usize = 128;
+pub const INF: i32 = 32_001;
+pub const MATE: i32 = 32_000;
+/// Any score above this is a forced mate
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+/// Margins and depth limits of the search, all set at runtime with the
+/// `tune` feature
+#[derive(Clone, Copy, Debug)]
+pub struct SearchParams {
+    /// Half width of the first aspiration window
+    pub aspiration_window: i32,
+    /// Shallower iterations are too unstable to bother with a window
+    pub aspiration_depth: i32,
+    /// How much the window widens after a fail, in percent
+    pub aspiration_growth: i32,
+    /// Captures that can't bring the score back up to alpha even with this
+    /// margin are skipped
+    pub delta_margin: i32,
+    /// Only nodes this shallow get reverse futility pruned
+    pub rfp_depth: i32,
+    pub rfp_margin: i32,
+    pub nmp_depth: i32,
+    /// Null move reduction: the base, one more ply per `nmp_depth_divisor`
+    /// of depth and per `nmp_eval_divisor` of eval above beta, up to
+    /// `nmp_eval_max` of those
+    pub nmp_base: i32,
+    pub nmp_depth_divisor: i32,
+    pub nmp_eval_divisor: i32,
+    pub nmp_eval_max: i32,
+    /// With at most this many pieces besides pawns and kings a null move
+    /// cutoff is verified, zugzwang is likely
+    pub nmp_verify_pieces: i32,
+    pub futility_depth: i32,
+    pub futility_margin: i32,
+    pub lmp_depth: i32,
+    /// Quiet moves tried before the rest get pruned, plus depth squared
+    pub lmp_base: i32,
+    pub lmr_depth: i32,
+    /// Reduction of `lmr_base + ln(depth) * ln(moves) / lmr_divisor`, both in
+    /// hundredths of a ply
+    pub lmr_base: i32,
+    pub lmr_divisor: i32,
+    /// Only TT moves this deep get tested for singularity
+    pub singular_depth: i32,
+    /// The TT entry can be that much shallower than the node
+    pub singular_tt_depth: i32,
+    /// How far below the TT score the other moves have to fail, per ply
+    pub singular_margin: i32,
+    /// History bonus per depth squared, and its maximum
+    pub history_bonus: i32,
+    pub history_max: i32,
+    /// Gravity keeps the history values within that
+    pub history_limit: i32,
+    /// Ordering scores, captures winning material are in between the TT move
+    /// and the killers, quiets go by history below those
+    pub order_tt_move: i32,
+    pub order_killer: i32,
+    pub order_countermove: i32,
+    /// Added to every thread's score above the worst one when voting, so
+    /// even that one counts
+    pub vote_offset: i32,
+    /// Time management, see `TimeManager`
+    pub tm_moves: i32,
+    pub tm_inc_percent: i32,
+    pub tm_hard_factor: i32,
+    pub tm_stability: i32,
+    pub tm_instability: i32,
+    /// How much of the instability is left after an iteration, in percent
+    pub tm_instability_decay: i32,
+    pub tm_drop_divisor: i32,
+    pub tm_min_scale: i32,
+    pub tm_max_scale: i32,
+}
+
+impl SearchParams {
+    pub const DEFAULT: SearchParams = SearchParams {
+        aspiration_window: 25,
+        aspiration_depth: 4,
+        aspiration_growth: 50,
+        delta_margin: 200,
+        rfp_depth: 6,
+        rfp_margin: 80,
+        nmp_depth: 3,
+        nmp_base: 3,
+        nmp_depth_divisor: 6,
+        nmp_eval_divisor: 200,
+        nmp_eval_max: 3,
+        nmp_verify_pieces: 1,
+        futility_depth: 3,
+        futility_margin: 120,
+        lmp_depth: 4,
+        lmp_base: 3,
+        lmr_depth: 3,
+        lmr_base: 75,
+        lmr_divisor: 225,
+        singular_depth: 8,
+        singular_tt_depth: 3,
+        singular_margin: 2,
+        history_bonus: 16,
+        history_max: 1600,
+        history_limit: 16384,
+        order_tt_move: 3_000_000,
+        order_killer: 1_000_000,
+        order_countermove: 900_000,
+        vote_offset: 14,
+        tm_moves: 30,
+        tm_inc_percent: 75,
+        tm_hard_factor: 4,
+        tm_stability: 80,
+        tm_instability: 40,
+        tm_instability_decay: 50,
+        tm_drop_divisor: 200,
+        tm_min_scale: 50,
+        tm_max_scale: 250,
+    };
+
+    /// Every parameter with its name, range and SPSA step size
+    #[cfg(feature = "tune")]
+    pub fn params(&mut self) -> [(&'static str, &mut i32, i32, i32, f64); 38] {
+        [
+            ("AspirationWindow", &mut self.aspiration_window, 5, 100, 5.0),
+            ("AspirationDepth", &mut self.aspiration_depth, 1, 10, 1.0),
+            (
+                "AspirationGrowth",
+                &mut self.aspiration_growth,
+                10,
+                200,
+                8.0,
+            ),
+            ("DeltaMargin", &mut self.delta_margin, 50, 500, 20.0),
+            ("RfpDepth", &mut self.rfp_depth, 1, 12, 1.0),
+            ("RfpMargin", &mut self.rfp_margin, 20, 200, 8.0),
+            ("NmpDepth", &mut self.nmp_depth, 1, 8, 0.5),
+            ("NmpBase", &mut self.nmp_base, 1, 6, 0.5),
+            ("NmpDepthDivisor", &mut self.nmp_depth_divisor, 2, 12, 0.5),
+            ("NmpEvalDivisor", &mut self.nmp_eval_divisor, 50, 500, 20.0),
+            ("NmpEvalMax", &mut self.nmp_eval_max, 0, 6, 0.5),
+            ("NmpVerifyPieces", &mut self.nmp_verify_pieces, 0, 4, 0.5),
+            ("FutilityDepth", &mut self.futility_depth, 1, 8, 0.5),
+            ("FutilityMargin", &mut self.futility_margin, 30, 300, 10.0),
+            ("LmpDepth", &mut self.lmp_depth, 1, 10, 0.5),
+            ("LmpBase", &mut self.lmp_base, 1, 10, 0.5),
+            ("LmrDepth", &mut self.lmr_depth, 1, 8, 0.5),
+            ("LmrBase", &mut self.lmr_base, 0, 200, 8.0),
+            ("LmrDivisor", &mut self.lmr_divisor, 100, 400, 15.0),
+            ("SingularDepth", &mut self.singular_depth, 4, 14, 1.0),
+            ("SingularTtDepth", &mut self.singular_tt_depth, 1, 6, 0.5),
+            ("SingularMargin", &mut self.singular_margin, 1, 6, 0.5),
+            ("HistoryBonus", &mut self.history_bonus, 4, 40, 2.0),
+            ("HistoryMax", &mut self.history_max, 400, 4000, 100.0),
+            ("HistoryLimit", &mut self.history_limit, 4096, 32768, 1024.0),
+            (
+                "OrderTtMove",
+                &mut self.order_tt_move,
+                2_100_000,
+                4_000_000,
+                50_000.0,
+            ),
+            (
+                "OrderKiller",
+                &mut self.order_killer,
+                100_000,
+                1_900_000,
+                50_000.0,
+            ),
+            (
+                "OrderCountermove",
+                &mut self.order_countermove,
+                0,
+                1_900_000,
+                50_000.0,
+            ),
+            ("VoteOffset", &mut self.vote_offset, 0, 50, 2.0),
+            ("TmMoves", &mut self.tm_moves, 10, 60, 2.0),
+            ("TmIncPercent", &mut self.tm_inc_percent, 25, 100, 5.0),
+            ("TmHardFactor", &mut self.tm_hard_factor, 2, 8, 0.5),
+            ("TmStability", &mut self.tm_stability, 40, 120, 5.0),
+            ("TmInstability", &mut self.tm_instability, 10, 100, 5.0),
+            (
+                "TmInstabilityDecay",
+                &mut self.tm_instability_decay,
+                10,
+                90,
+                4.0,
+            ),
+            ("TmDropDivisor", &mut self.tm_drop_divisor, 50, 500, 20.0),
+            ("TmMinScale", &mut self.tm_min_scale, 20, 100, 5.0),
+            ("TmMaxScale", &mut self.tm_max_scale, 100, 400, 15.0),
+        ]
+    }
+}
+
+/// Depth of deterministic searches that only had a time limit
+pub const DETERMINISTIC_DEPTH: u8 = 10;
+
+pub const DEFAULT_EXTENSION_BUDGET: u8 = 16;
+pub const MAX_EXTENSION_BUDGET: u8 = 64;
+
+pub const DEFAULT_THREADS: usize = 1;
+pub const MAX_THREADS: usize = 256;
+pub const MAX_MULTI_PV: usize = 256;
+/// Helper threads skip some iterations, so they aren't all searching the same
+/// depth at the same time. Indexed by helper number.
+const SKIP_SIZE: [u8; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u8; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+/// Late move reductions by depth and move number, log based
+fn lmr_table(p: &SearchParams) -> [[u8; 64]; 64] {
+    let mut table = [[0; 64]; 64];
+    for (d, row) in table.iter_mut().enumerate().skip(1) {
+        for (m, r) in row.iter_mut().enumerate().skip(1) {
+            let log = (d as f64).ln() * (m as f64).ln();
+            *r = (p.lmr_base as f64 / 100. + log * 100. / p.lmr_divisor as f64) as u8;
+        }
+    }
+    table
+}
+
+/// Behaviour switches set through `setoption`
+#[derive(Clone, Copy)]
+pub struct SearchOptions {
+    /// Also try quiet checking moves at the first quiescence ply
+    pub qsearch_checks: bool,
+    pub null_move: bool,
+    pub lmr: bool,
+    pub futility: bool,
+    pub reverse_futility: bool,
+    pub late_move_pruning: bool,
+    /// Killers, countermoves and history, or just the TT move first
+    pub move_ordering: bool,
+    pub mate_distance_pruning: bool,
+    /// `go mate` only tries checking moves for the side that mates
+    pub mate_checks_only: bool,
+    /// Reproducible searches for testing, see `Search::search`
+    pub deterministic: bool,
+    pub check_extension: bool,
+    /// Extend the TT move when a reduced search without it fails low
+    pub singular_extension: bool,
+    /// Extend a capture on the square the previous move captured on
+    pub recapture_extension: bool,
+    /// Extend pawn pushes to the seventh rank
+    pub passed_pawn_extension: bool,
+    /// Evaluate king safety, off to measure what it's worth
+    pub king_safety: bool,
+    /// Plies any single line can be extended by in total
+    pub extension_budget: u8,
+    /// Time in ms lost per move to the GUI and the network
+    pub move_overhead: u64,
+    pub threads: usize,
+    /// Number of best root moves to report
+    pub multi_pv: usize,
+    /// Margins and depth limits, set one by one with the `tune` feature
+    pub params: SearchParams,
+}
+
+impl SearchOptions {
+    /// The on/off switches, by their UCI name
+    pub fn switches(&mut self) -> [(&'static str, &mut bool); 15] {
+        [
+            ("QSearchChecks", &mut self.qsearch_checks),
+            ("NullMove", &mut self.null_move),
+            ("LMR", &mut self.lmr),
+            ("Futility", &mut self.futility),
+            ("ReverseFutility", &mut self.reverse_futility),
+            ("LateMovePruning", &mut self.late_move_pruning),
+            ("MoveOrdering", &mut self.move_ordering),
+            ("MateDistancePruning", &mut self.mate_distance_pruning),
+            ("MateChecksOnly", &mut self.mate_checks_only),
+            ("Deterministic", &mut self.deterministic),
+            ("CheckExtension", &mut self.check_extension),
+            ("SingularExtension", &mut self.singular_extension),
+            ("RecaptureExtension", &mut self.recapture_extension),
+            ("PassedPawnExtension", &mut self.passed_pawn_extension),
+            ("KingSafety", &mut self.king_safety),
+        ]
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            qsearch_checks: false,
+            null_move: true,
+            lmr: true,
+            futility: true,
+            reverse_futility: true,
+            late_move_pruning: true,
+            move_ordering: true,
+            mate_distance_pruning: true,
+            mate_checks_only: false,
+            deterministic: false,
+            check_extension: true,
+            singular_extension: true,
+            recapture_extension: false,
+            passed_pawn_extension: false,
+            king_safety: true,
+            extension_budget: DEFAULT_EXTENSION_BUDGET,
+            move_overhead: DEFAULT_MOVE_OVERHEAD,
+            threads: DEFAULT_THREADS,
+            multi_pv: 1,
+            params: SearchParams::DEFAULT,
+        }
+    }
+}
+
+/// Flags the UCI thread uses to steer a running search
+#[derive(Default)]
+pub struct Signals {
+    pub stop: AtomicBool,
+    /// While set the time limit is ignored, `ponderhit` clears it
+    pub ponder: AtomicBool,
+}
+
+/// State the threads of a single search share, besides the TT
+pub(crate) struct Shared<'a> {
+    /// Set once the main thread is done, to stop the helpers
+    finished: AtomicBool,
+    /// Nodes searched so far by every thread, published every few nodes
+    nodes: Vec<AtomicU64>,
+    /// Evaluates instead of the handcrafted eval when loaded
+    net: Option<&'a Network>,
+    /// Of the handcrafted eval
+    weights: &'a Weights,
+}
+
+impl<'a> Shared<'a> {
+    pub(crate) fn new(threads: usize, net: Option<&'a Network>, weights: &'a Weights) -> Self {
+        Shared {
+            finished: AtomicBool::new(false),
+            nodes: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+            net,
+            weights,
+        }
+    }
+}
+
+/// What a finished search has to say
+pub struct SearchResult {
+    /// None when there is no legal move
+    pub best: Option<Move>,
+    /// The reply expected to `best`, to ponder on
+    pub ponder: Option<Move>,
+    pub score: i32,
+    /// The last completed depth
+    pub depth: u8,
+    /// Nodes searched by all threads together
+    pub nodes: u64,
+}
+
+/// A root move and the line following it, from a completed iteration
+struct PvLine {
+    depth: u8,
+    score: i32,
+    pv: Vec<Move>,
+}
+
+pub struct Search<'a> {
+    /// 0 for the main thread, which manages time and prints, helpers count up from 1
+    id: usize,
+    tt: &'a TranspositionTable,
+    signals: &'a Signals,
+    shared: &'a Shared<'a>,
+    options: SearchOptions,
+    start: Instant,
+    tm: TimeManager,
+    limits: SearchLimits,
+    pondering: bool,
+    depth: u8,
+    nodes: u64,
+    seldepth: usize,
+    stopped: bool,
+    root_best: Move,
+    root_legal: usize,
+    /// The MultiPV line being searched, 0 for the best one
+    pv_idx: usize,
+    /// Root moves of the lines already found in this iteration
+    excluded: Vec<Move>,
+    history: &'a mut History,
+    /// The move made at every ply of the current line
+    played: [PlayedMove; MAX_PLY],
+    /// Material and piece-square sums of the position at every ply
+    psqt: [Psqt; MAX_PLY + 1],
+    /// Zobrist keys of the position at every ply
+    keys: [Keys; MAX_PLY + 1],
+    pawns: PawnTable,
+    /// Network accumulators of every ply, only used with a network
+    acc: Vec<Accumulator>,
+    /// Set at the plies where the move made was a capture
+    captured: [bool; MAX_PLY],
+    /// Late move reductions by depth and move number
+    lmr: [[u8; 64]; 64],
+    /// Plies the line leading to every ply has been extended by
+    extended: [u8; MAX_PLY],
+    /// The TT move being verified as singular at this ply, left out of the move loop
+    singular_move: [Move; MAX_PLY],
+    /// Set at the plies where a null move was made
+    null_moved: [bool; MAX_PLY],
+    /// Inside a null move verification search
+    verifying: bool,
+    pv: [[Move; MAX_PLY]; MAX_PLY],
+    pv_len: [usize; MAX_PLY],
+}
+
+impl<'a> Search<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        id: usize,
+        limits: SearchLimits,
+        tm: TimeManager,
+        tt: &'a TranspositionTable,
+        signals: &'a Signals,
+        shared: &'a Shared<'a>,
+        options: SearchOptions,
+        history: &'a mut History,
+    ) -> Self {
+        Search {
+            id,
+            tt,
+            signals,
+            shared,
+            options,
+            start: Instant::now(),
+            tm,
+            limits,
+            pondering: signals.ponder.load(Ordering::Relaxed),
+            depth: 0,
+            nodes: 0,
+            seldepth: 0,
+            stopped: false,
+            root_best: Move::default(),
+            root_legal: 0,
+            pv_idx: 0,
+            excluded: Vec::new(),
+            history,
+            played: [None; MAX_PLY],
+            psqt: [Psqt::default(); MAX_PLY + 1],
+            keys: [Keys::default(); MAX_PLY + 1],
+            pawns: PawnTable::new(),
+            acc: match shared.net {
+                Some(_) => vec![Accumulator::default(); MAX_PLY + 1],
+                None => Vec::new(),
+            },
+            captured: [false; MAX_PLY],
+            lmr: lmr_table(&options.params),
+            extended: [0; MAX_PLY],
+            singular_move: [Move::default(); MAX_PLY],
+            null_moved: [false; MAX_PLY],
+            verifying: false,
+            pv: [[Move::default(); MAX_PLY]; MAX_PLY],
+            pv_len: [0; MAX_PLY],
+        }
+    }
+
+    /// Lazy SMP: one thread per history in `histories`, all sharing the TT and
+    /// otherwise searching on their own.
+    ///
+    /// When pondering or searching infinitely this doesn't return before the
+    /// GUI sends `stop` or `ponderhit`, even if the search itself is done.
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(
+        limits: SearchLimits,
+        b: Board,
+        tt: &TranspositionTable,
+        signals: &Signals,
+        options: SearchOptions,
+        histories: &mut [History],
+        net: Option<&Network>,
+        weights: &Weights,
+    ) -> SearchResult {
+        let mut limits = limits;
+        let histories = if options.deterministic {
+            // Same position and limits, same result: nothing carried over from
+            // earlier searches, no other threads and no clock
+            tt.clear();
+            histories[0].clear();
+            let timed = !limits.unlimited();
+            limits.time = [None; 2];
+            limits.movetime = None;
+            if timed && limits.unlimited() {
+                // The clock was the only limit, a fixed depth stands in for it
+                limits.depth = Some(DETERMINISTIC_DEPTH);
+            }
+            &mut histories[..1]
+        } else {
+            histories
+        };
+        if let Some(moves) = limits.mate {
+            let mut mate = MateSearch::new(signals, options.mate_checks_only);
+            if let Some(pv) = mate.search(&b, moves) {
+                return SearchResult {
+                    best: Some(pv[0]),
+                    ponder: pv.get(1).copied(),
+                    score: MATE - pv.len() as i32,
+                    depth: pv.len() as u8,
+                    nodes: mate.nodes(),
+                };
+            }
+            println!("info string no mate in {moves} found");
+            // Still have to come up with a move
+            let plies = (2 * moves).saturating_sub(1).clamp(1, MAX_PLY as u32 - 1);
+            limits.depth.get_or_insert(plies as u8);
+        }
+        tt.new_search();
+        let shared = Shared::new(histories.len(), net, weights);
+        let (main_history, helper_histories) = histories
+            .split_first_mut()
+            .expect("searching needs at least one thread");
+        let (main, results) = thread::scope(|scope| {
+            let helpers = helper_histories
+                .iter_mut()
+                .enumerate()
+                .map(|(i, history)| {
+                    // Helpers only stop when the main thread does
+                    let limits = SearchLimits {
+                        time: [None; 2],
+                        movetime: None,
+                        nodes: None,
+                        infinite: true,
+                        ..limits.clone()
+                    };
+                    let (tm, shared) = (
+                        TimeManager::new(&limits, b.color, 0, &options.params),
+                        &shared,
+                    );
+                    // MultiPV lines are only reported by the main thread
+                    let options = SearchOptions {
+                        multi_pv: 1,
+                        ..options
+                    };
+                    scope.spawn(move || {
+                        history.age();
+                        Search::new(i + 1, limits, tm, tt, signals, shared, options, history)
+                            .iterate(&b)
+                    })
+                })
+                .collect::<Vec<_>>();
+            main_history.age();
+            let tm = TimeManager::new(&limits, b.color, options.move_overhead, &options.params);
+            let mut main = Search::new(0, limits, tm, tt, signals, &shared, options, main_history);
+            let mut results = vec![main.iterate(&b)];
+            shared.finished.store(true, Ordering::Relaxed);
+            for helper in helpers {
+                results.push(helper.join().expect("search thread panicked"));
+            }
+            (main, results)
+        });
+
+        // With MultiPV the main thread's lines have been reported, stick to them
+        let best = if options.multi_pv == 1 {
+            Self::vote(&results, &options.params)
+        } else {
+            0
+        };
+        if best != 0 {
+            let r = &results[best];
+            let pv = r.pv.iter().map(|m| format!(" {}", m.as_text()));
+            println!(
+                "info depth {} score {} nodes {} pv{}",
+                r.depth,
+                score_to_uci(r.score),
+                main.total_nodes(),
+                pv.collect::<String>()
+            );
+        }
+        let line = &results[best];
+        let result = SearchResult {
+            // Not even depth 1 finished, fall back to whatever the root liked so
+            // far or else any legal move
+            best: line.pv.first().copied().or_else(|| {
+                let liked = Some(main.root_best).filter(|m| *m != Move::default());
+                liked.or_else(|| {
+                    let mut moves = b.gen_pseudo_legal().into_iter();
+                    moves.find(|m| {
+                        let mut new_b = b;
+                        new_b.make_move(m)
+                    })
+                })
+            }),
+            ponder: line.pv.get(1).copied(),
+            score: line.score,
+            depth: line.depth,
+            nodes: main.total_nodes(),
+        };
+        while main.waiting_for_gui() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        result
+    }
+
+    /// Picks the thread to take the best move from. Every thread votes for its
+    /// move with a weight growing with depth and score, a proven mate beats
+    /// everything but a shorter one.
+    fn vote(results: &[PvLine], p: &SearchParams) -> usize {
+        let finished = || results.iter().filter(|r| !r.pv.is_empty());
+        let min_score = finished().map(|r| r.score).min().unwrap_or(0);
+        let votes = |m: Move| {
+            finished()
+                .filter(|r| r.pv[0] == m)
+                .map(|r| (r.score - min_score + p.vote_offset) as i64 * r.depth as i64)
+                .sum::<i64>()
+        };
+        let mut best = 0;
+        for (i, r) in results.iter().enumerate().skip(1) {
+            if r.pv.is_empty() {
+                continue;
+            }
+            let current = &results[best];
+            let better = if current.pv.is_empty() {
+                true
+            } else if current.score >= MATE_BOUND || r.score >= MATE_BOUND {
+                r.score > current.score
+            } else {
+                votes(r.pv[0]) > votes(current.pv[0])
+            };
+            if better {
+                best = i;
+            }
+        }
+        best
+    }
+
+    /// Helpers skip depths following their row of `SKIP_SIZE` and `SKIP_PHASE`
+    fn skips(&self, depth: u8) -> bool {
+        if self.id == 0 {
+            return false;
+        }
+        let i = (self.id - 1) % SKIP_SIZE.len();
+        !((depth + SKIP_PHASE[i]) / SKIP_SIZE[i]).is_multiple_of(2)
+    }
+
+    /// Iterative deepening, returns the best line of the last completed iteration.
+    ///
+    /// With MultiPV every iteration searches the root once per line, each time
+    /// without the root moves of the lines found before.
+    fn iterate(&mut self, b: &Board) -> PvLine {
+        let max_depth = self
+            .limits
+            .depth
+            .unwrap_or(MAX_PLY as u8 - 1)
+            .min(MAX_PLY as u8 - 1);
+        let mut lines: Vec<PvLine> = Vec::new();
+        self.psqt[0] = Psqt::new(&self.shared.weights.psqt, b);
+        self.keys[0] = Keys::new(b);
+        if let Some(net) = self.shared.net {
+            self.acc[0] = Accumulator::new(net, b);
+        }
+        for depth in 1..=max_depth {
+            if self.skips(depth) {
+                continue;
+            }
+            self.depth = depth;
+            self.seldepth = 0;
+            self.excluded.clear();
+            let mut new_lines = Vec::new();
+            for pv_idx in 0..self.options.multi_pv {
+                if pv_idx > 0 && pv_idx >= self.root_legal {
+                    break;
+                }
+                self.pv_idx = pv_idx;
+                let prev_score = lines.get(pv_idx).map(|l| l.score);
+                let score = self.aspiration(b, depth, prev_score);
+                if self.stopped {
+                    break;
+                }
+                let pv = self.pv[0][..self.pv_len[0]].to_vec();
+                self.excluded.extend(pv.first());
+                new_lines.push(PvLine { depth, score, pv });
+            }
+            if self.stopped {
+                break;
+            }
+            new_lines.sort_by_key(|l| -l.score);
+            for (pv_idx, line) in new_lines.iter().enumerate() {
+                self.print_line(pv_idx, line.score, Bound::Exact, &line.pv);
+            }
+            let prev_score = lines.first().map(|l| l.score);
+            let best_changed = lines.first().map(|l| l.pv.first()) != Some(new_lines[0].pv.first());
+            lines = new_lines;
+            let score = lines[0].score;
+            if self.root_legal == 0 {
+                // Mate or stalemate, there is nothing to search
+                break;
+            }
+            self.tm.update(best_changed && depth > 1, score, prev_score);
+            if self.mate_found(score) {
+                break;
+            }
+            if self.id > 0 {
+                continue;
+            }
+            if self.time_up(true) {
+                break;
+            }
+            if self.root_legal == 1 && self.tm.timed() && !self.pondering {
+                // Nothing to think about
+                break;
+            }
+        }
+        self.shared.nodes[self.id].store(self.nodes, Ordering::Relaxed);
+        lines.into_iter().next().unwrap_or(PvLine {
+            depth: 0,
+            score: 0,
+            pv: Vec::new(),
+        })
+    }
+
+    /// Principal variation search, nodes with a zero window are expected to fail
+    fn negamax(&mut self, b: &Board, depth: u8, ply: usize, mut alpha: i32, mut beta: i32) -> i32 {
+        self.pv_len[ply] = ply;
+        // Re-searches after a stop mustn't count, the node limit is exact
+        if self.stopped {
+            return 0;
+        }
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        self.check_time();
+        if self.stopped {
+            return 0;
+        }
+        if ply > 0 && self.options.mate_distance_pruning {
+            // Nothing found here can beat a mate already found closer to the root
+            alpha = alpha.max(-MATE + ply as i32);
+            beta = beta.min(MATE - ply as i32 - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(b, ply, alpha, beta, 0);
+        }
+        let pv_node = beta - alpha > 1;
+
+        let hash = self.keys[ply].hash;
+        let singular_search = self.singular_move[ply] != Move::default();
+        let tt_entry = self.tt.probe(hash);
+        let mut tt_move = Move::default();
+        if let Some(entry) = tt_entry {
+            tt_move = entry.mv;
+            let score = score_from_tt(entry.score, ply);
+            // Cutting PV nodes would leave holes in the reported PV, and the
+            // entry doesn't know about the move left out of a singular search
+            if !pv_node && !singular_search && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => (),
+                }
+            }
+        }
+
+        let in_check = b.in_check();
+        let static_eval = if in_check {
+            -INF
+        } else {
+            self.evaluate(b, ply)
+        };
+        let prunable =
+            !pv_node && !in_check && !singular_search && ply > 0 && beta.abs() < MATE_BOUND;
+
+        if prunable
+            && self.options.reverse_futility
+            && depth as i32 <= self.options.params.rfp_depth
+            && static_eval - self.options.params.rfp_margin * depth as i32 >= beta
+        {
+            return static_eval;
+        }
+
+        if prunable
+            && self.options.null_move
+            && !self.null_moved[ply - 1]
+            && !self.verifying
+            && depth as i32 >= self.options.params.nmp_depth
+            && static_eval >= beta
+            && !b.non_pawn_material().empty()
+        {
+            let p = &self.options.params;
+            let r = p.nmp_base
+                + depth as i32 / p.nmp_depth_divisor
+                + ((static_eval - beta) / p.nmp_eval_divisor).min(p.nmp_eval_max);
+            let r = r.clamp(0, u8::MAX as i32 - 1) as u8;
+            let mut null_b = *b;
+            null_b.make_null_move();
+            self.null_moved[ply] = true;
+            self.played[ply] = None;
+            self.captured[ply] = false;
+            self.extended[ply + 1] = self.extended[ply];
+            self.psqt[ply + 1] = self.psqt[ply];
+            self.update_ply(b, &null_b, ply);
+            let null_depth = depth.saturating_sub(r + 1);
+            let mut score = -self.negamax(&null_b, null_depth, ply + 1, -beta, -beta + 1);
+            self.null_moved[ply] = false;
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                if score >= MATE_BOUND {
+                    score = beta;
+                }
+                // Zugzwang is common with few pieces left, make sure the side to
+                // move really can do without its move
+                let low_material = b.non_pawn_material().0.count_ones() as i32
+                    <= self.options.params.nmp_verify_pieces;
+                if !low_material {
+                    return score;
+                }
+                self.verifying = true;
+                let verified = self.negamax(b, null_depth, ply, beta - 1, beta);
+                self.verifying = false;
+                if verified >= beta {
+                    return score;
+                }
+            }
+        }
+
+        let futile = prunable
+            && self.options.futility
+            && depth as i32 <= self.options.params.futility_depth
+            && static_eval + self.options.params.futility_margin * depth as i32 <= alpha;
+        let lmp_limit = if prunable
+            && self.options.late_move_pruning
+            && depth as i32 <= self.options.params.lmp_depth
+        {
+            self.options.params.lmp_base as usize + depth as usize * depth as usize
+        } else {
+            usize::MAX
+        };
+
+        // The TT move is singular if everything else fails low against a bound
+        // somewhat below its score
+        let can_extend = self.extended[ply] < self.options.extension_budget;
+        let singular = match tt_entry {
+            Some(entry)
+                if can_extend
+                    && self.options.singular_extension
+                    && ply > 0
+                    && !singular_search
+                    && depth as i32 >= self.options.params.singular_depth
+                    && entry.mv != Move::default()
+                    && entry.bound != Bound::Upper
+                    && entry.depth as i32 + self.options.params.singular_tt_depth
+                        >= depth as i32
+                    && entry.score.abs() < MATE_BOUND =>
+            {
+                let singular_beta =
+                    entry.score - self.options.params.singular_margin * depth as i32;
+                self.singular_move[ply] = entry.mv;
+                let score = self.negamax(b, (depth - 1) / 2, ply, singular_beta - 1, singular_beta);
+                self.singular_move[ply] = Move::default();
+                if self.stopped {
+                    return 0;
+                }
+                score < singular_beta
+            }
+            _ => false,
+        };
+
+        let prev = if ply > 0 { self.played[ply - 1] } else { None };
+        let moves = if self.options.move_ordering {
+            self.history.order(
+                b,
+                b.gen_pseudo_legal(),
+                tt_move,
+                ply,
+                prev,
+                &self.options.params,
+            )
+        } else {
+            let mut moves = b.gen_pseudo_legal();
+            if let Some(i) = moves.moves.iter().position(|m| *m == tt_move) {
+                moves.moves.swap(0, i);
+            }
+            moves
+                .into_iter()
+                .map(|m| (m, b.piece_on(m.get_from()).unwrap_or(Piece::Pawn)))
+                .collect()
+        };
+        let orig_alpha = alpha;
+        let mut best_score = -INF;
+        let mut best_move = Move::default();
+        let mut legal = 0;
+        let mut quiets_tried = Vec::new();
+        for (m, piece) in moves {
+            if ply == 0
+                && ((!self.limits.searchmoves.is_empty() && !self.limits.searchmoves.contains(&m))
+                    || self.excluded.contains(&m))
+            {
+                continue;
+            }
+            if m == self.singular_move[ply] {
+                continue;
+            }
+            let quiet = !b.is_tactical(&m);
+            let capture = b.captured_piece(&m).is_some();
+            let mut new_b = *b;
+            let mut psqt = self.psqt[ply];
+            if !new_b.make_move_psqt(&m, &mut psqt, &self.shared.weights.psqt) {
+                continue;
+            }
+            self.psqt[ply + 1] = psqt;
+            self.update_ply(b, &new_b, ply);
+            legal += 1;
+            self.played[ply] = Some((m, piece));
+            self.captured[ply] = capture;
+            let gives_check = new_b.in_check();
+            if quiet {
+                quiets_tried.push((m, piece));
+            }
+            if quiet
+                && !gives_check
+                && legal > 1
+                && best_score > -MATE_BOUND
+                && (futile || quiets_tried.len() > lmp_limit)
+            {
+                continue;
+            }
+
+            let extension = can_extend
+                && ((m == tt_move && singular)
+                    || (gives_check && self.options.check_extension)
+                    || (self.options.recapture_extension && capture && self.recaptures(ply, &m))
+                    || (self.options.passed_pawn_extension
+                        && piece == Piece::Pawn
+                        && Self::to_seventh_rank(b, &m)));
+            self.extended[ply + 1] = self.extended[ply] + extension as u8;
+            let new_depth = depth - 1 + extension as u8;
+
+            let mut score;
+            if legal == 1 {
+                score = -self.negamax(&new_b, new_depth, ply + 1, -beta, -alpha);
+            } else {
+                let mut reduction = 0;
+                if self.options.lmr
+                    && depth as i32 >= self.options.params.lmr_depth
+                    && quiet
+                    && !in_check
+                    && !gives_check
+                {
+                    let r = self.lmr[(depth as usize).min(63)][legal.min(63)];
+                    reduction = r.saturating_sub(pv_node as u8);
+                    reduction = reduction.min(new_depth);
+                }
+                score = -self.negamax(&new_b, new_depth - reduction, ply + 1, -alpha - 1, -alpha);
+                if reduction > 0 && score > alpha {
+                    score = -self.negamax(&new_b, new_depth, ply + 1, -alpha - 1, -alpha);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(&new_b, new_depth, ply + 1, -beta, -alpha);
+                }
+            }
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = m;
+                self.update_pv(ply, m);
+                if ply == 0 {
+                    let changed = self.root_best != m;
+                    self.root_best = m;
+                    if changed && legal > 1 && self.depth > 1 {
+                        self.print_info(score, Bound::Lower);
+                    }
+                }
+                if alpha >= beta {
+                    if quiet {
+                        self.history.update(
+                            b,
+                            (m, piece),
+                            &quiets_tried,
+                            ply,
+                            prev,
+                            depth,
+                            &self.options.params,
+                        );
+                    }
+                    break;
+                }
+            }
+        }
+        if ply == 0 && self.pv_idx == 0 {
+            self.root_legal = legal;
+        }
+        if legal == 0 {
+            return if singular_search {
+                // The TT move was the only legal one, that's as singular as it gets
+                alpha
+            } else if in_check {
+                -MATE + ply as i32
+            } else {
+                0
+            };
+        }
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > orig_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // A node searched with some moves left out doesn't get its real score
+        let partial = if ply == 0 {
+            !self.limits.searchmoves.is_empty() || !self.excluded.is_empty()
+        } else {
+            singular_search
+        };
+        if !partial {
+            self.tt
+                .store(hash, best_move, score_to_tt(best_score, ply), depth, bound);
+        }
+        best_score
+    }
+
+    /// Captures back on the square the previous move captured on
+    fn recaptures(&self, ply: usize, m: &Move) -> bool {
+        ply > 0
+            && self.captured[ply - 1]
+            && self.played[ply - 1].is_some_and(|(prev, _)| prev.get_to() == m.get_to())
+    }
+
+    fn to_seventh_rank(b: &Board, m: &Move) -> bool {
+        let rank = m.get_to_idx().0 / 8;
+        match b.color {
+            Color::White => rank == 6,
+            Color::Black => rank == 1,
+        }
+    }
+
+    /// Searches a window around the last score, widening it on every fail
+    fn aspiration(&mut self, b: &Board, depth: u8, prev_score: Option<i32>) -> i32 {
+        let mut delta = self.options.params.aspiration_window;
+        let (mut alpha, mut beta) = match prev_score {
+            Some(score)
+                if depth as i32 >= self.options.params.aspiration_depth
+                    && score.abs() < MATE_BOUND =>
+            {
+                ((score - delta).max(-INF), (score + delta).min(INF))
+            }
+            _ => (-INF, INF),
+        };
+        loop {
+            let score = self.negamax(b, depth, 0, alpha, beta);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha {
+                self.print_info(score, Bound::Upper);
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INF);
+            } else if score >= beta {
+                self.print_info(score, Bound::Lower);
+                beta = (score + delta).min(INF);
+            } else {
+                return score;
+            }
+            delta += delta * self.options.params.aspiration_growth / 100;
+        }
+    }
+
+    /// Static evaluation of `b` at `ply`, by the network if there is one
+    fn evaluate(&mut self, b: &Board, ply: usize) -> i32 {
+        match self.shared.net {
+            Some(net) => net
+                .evaluate(&self.acc[ply], b.color)
+                .clamp(-MATE_BOUND + 1, MATE_BOUND - 1),
+            None => b.evaluate(
+                self.shared.weights,
+                &self.psqt[ply],
+                self.keys[ply].pawns,
+                &mut self.pawns,
+                self.options.king_safety,
+            ),
+        }
+    }
+
+    /// Brings the keys and the accumulator of the next ply up to date after
+    /// `b` at `ply` turned into `new_b`
+    fn update_ply(&mut self, b: &Board, new_b: &Board, ply: usize) {
+        self.keys[ply + 1] = self.keys[ply];
+        self.keys[ply + 1].update(b, new_b);
+        if let Some(net) = self.shared.net {
+            let (done, next) = self.acc.split_at_mut(ply + 1);
+            next[0] = done[ply];
+            next[0].update(net, b, new_b);
+        }
+    }
+
+    fn check_time(&mut self) {
+        if self.nodes.is_multiple_of(1024) {
+            self.shared.nodes[self.id].store(self.nodes, Ordering::Relaxed);
+            if self.id > 0 && self.shared.finished.load(Ordering::Relaxed) {
+                self.stopped = true;
+            }
+        }
+        if self.depth <= 1 {
+            return;
+        }
+        if self.limits.nodes.is_some_and(|n| self.total_nodes() >= n) {
+            self.stopped = true;
+        }
+        if self.nodes.is_multiple_of(1024) && self.time_up(false) {
+            self.stopped = true;
+        }
+    }
+
+    /// `go mate` is satisfied once a mate at least that short is proven
+    fn mate_found(&self, score: i32) -> bool {
+        self.limits
+            .mate
+            .is_some_and(|n| score >= MATE_BOUND && (MATE - score + 1) / 2 <= n as i32)
+    }
+
+    /// Checks the soft limit between iterations, the hard one inside them
+    fn time_up(&mut self, soft: bool) -> bool {
+        if self.signals.stop.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.pondering && !self.signals.ponder.load(Ordering::Relaxed) {
+            // ponderhit, the clock starts running now
+            self.pondering = false;
+            self.tm.restart();
+        }
+        if self.pondering {
+            return false;
+        }
+        if soft {
+            self.tm.soft_limit_reached()
+        } else {
+            self.tm.hard_limit_reached()
+        }
+    }
+
+    /// Own nodes exactly, the other threads' as of their last publication
+    fn total_nodes(&self) -> u64 {
+        let others = self.shared.nodes.iter().enumerate();
+        let others = others.filter(|(id, _)| *id != self.id);
+        self.nodes + others.map(|(_, n)| n.load(Ordering::Relaxed)).sum::<u64>()
+    }
+
+    fn waiting_for_gui(&self) -> bool {
+        let pondering = self.signals.ponder.load(Ordering::Relaxed);
+        (self.limits.unlimited() || pondering) && !self.signals.stop.load(Ordering::Relaxed)
+    }
+
+    /// Resolves captures and promotions so leaves aren't evaluated mid exchange
+    fn quiescence(&mut self, b: &Board, ply: usize, mut alpha: i32, beta: i32, qply: u8) -> i32 {
+        self.pv_len[ply] = ply;
+        if self.stopped {
+            return 0;
+        }
+        if qply > 0 {
+            self.nodes += 1;
+            self.seldepth = self.seldepth.max(ply);
+            self.check_time();
+        }
+        if self.stopped {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.evaluate(b, ply);
+        }
+
+        let in_check = b.in_check();
+        let mut best = -INF;
+        let mut stand_pat = -INF;
+        if !in_check {
+            stand_pat = self.evaluate(b, ply);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best = stand_pat;
+        }
+        let try_checks = !in_check && qply == 0 && self.options.qsearch_checks;
+
+        let mut moves = b
+            .gen_pseudo_legal()
+            .into_iter()
+            .filter(|m| in_check || try_checks || b.is_tactical(m))
+            .map(|m| (m, mvv_lva(b, &m)))
+            .collect::<Vec<_>>();
+        moves.sort_by_key(|(_, score)| -score);
+
+        let mut legal = 0;
+        for (m, _) in moves {
+            let tactical = b.is_tactical(&m);
+            if !in_check && tactical && !m.is_promotion() {
+                let gain = b.captured_piece(&m).map_or(0, |p| SEE_VALUES[p as usize]);
+                if stand_pat + gain + self.options.params.delta_margin <= alpha {
+                    continue;
+                }
+            }
+            if !in_check && b.see(&m) < 0 {
+                continue;
+            }
+            let mut new_b = *b;
+            let mut psqt = self.psqt[ply];
+            if !new_b.make_move_psqt(&m, &mut psqt, &self.shared.weights.psqt) {
+                continue;
+            }
+            self.psqt[ply + 1] = psqt;
+            self.update_ply(b, &new_b, ply);
+            legal += 1;
+            if !in_check && !tactical && !new_b.in_check() {
+                continue;
+            }
+            let score = -self.quiescence(&new_b, ply + 1, -beta, -alpha, qply + 1);
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, m);
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        if in_check && legal == 0 {
+            return -MATE + ply as i32;
+        }
+        best
+    }
+
+    fn update_pv(&mut self, ply: usize, m: Move) {
+        self.pv[ply][ply] = m;
+        for i in ply + 1..self.pv_len[ply + 1] {
+            self.pv[ply][i] = self.pv[ply + 1][i];
+        }
+        self.pv_len[ply] = self.pv_len[ply + 1].max(ply + 1);
+    }
+
+    /// Prints the line being searched when it fails high or low. With MultiPV
+    /// only the final lines of every iteration are reported, the bounds of
+    /// lines still being searched would show up once per re-search.
+    fn print_info(&self, score: i32, bound: Bound) {
+        if self.options.multi_pv > 1 {
+            return;
+        }
+        self.print_line(self.pv_idx, score, bound, &self.pv[0][..self.pv_len[0]]);
+    }
+
+    fn print_line(&self, pv_idx: usize, score: i32, bound: Bound, pv: &[Move]) {
+        if self.id != 0 {
+            return;
+        }
+        let nodes = self.total_nodes();
+        let elapsed = self.start.elapsed();
+        let nps = (nodes as u128 * 1000) / elapsed.as_millis().max(1);
+        let bound = match bound {
+            Bound::Exact => "",
+            Bound::Lower => " lowerbound",
+            Bound::Upper => " upperbound",
+        };
+        // A fail low at the root leaves no PV behind
+        let pv = pv
+            .iter()
+            .map(|m| format!(" {}", m.as_text()))
+            .collect::<String>();
+        let pv = if pv.is_empty() {
+            pv
+        } else {
+            format!(" pv{pv}")
+        };
+        // Plain single PV output doesn't mention the line number
+        let multipv = if self.options.multi_pv > 1 {
+            format!(" multipv {}", pv_idx + 1)
+        } else {
+            String::new()
+        };
+        println!(
+            "info depth {} seldepth {}{} score {}{} nodes {} nps {} time {} hashfull {}{}",
+            self.depth,
+            self.seldepth,
+            multipv,
+            score_to_uci(score),
+            bound,
+            nodes,
+            nps,
+            elapsed.as_millis(),
+            self.tt.hashfull(),
+            pv
+        );
+    }
+}
+
+/// Formats a score as `cp <x>` or `mate <moves>`
+pub fn score_to_uci(score: i32) -> String {
+    if score >= MATE_BOUND {
+        format!("mate {}", (MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("mate {}", -(MATE + score) / 2)
+    } else {
+        format!("cp {score}")
+    }
+}
+
+#[test]
+fn test_score_to_uci() {
+    assert_eq!(score_to_uci(35), "cp 35");
+    assert_eq!(score_to_uci(MATE - 1), "mate 1");
+    assert_eq!(score_to_uci(MATE - 3), "mate 2");
+    assert_eq!(score_to_uci(-MATE + 2), "mate -1");
+}
+#[test]
+fn test_move_ordering_nodes() {
+    use crate::utils::TestSearch;
+    let positions = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "1k6/2pp4/1p2p3/4q3/1P2B3/2PK1R2/8/8 w - - 0 46",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+    ];
+    let nodes = |fen: &str, move_ordering: bool| {
+        let options = SearchOptions {
+            move_ordering,
+            ..Default::default()
+        };
+        TestSearch::new(1).with_search(fen, "depth 6", options, |b, s| {
+            let mut prev_score = None;
+            for depth in 1..=6 {
+                s.depth = depth;
+                prev_score = Some(s.aspiration(b, depth, prev_score));
+            }
+            s.nodes
+        })
+    };
+    let (mut ordered, mut unordered) = (0, 0);
+    for fen in positions {
+        ordered += nodes(fen, true);
+        unordered += nodes(fen, false);
+    }
+    assert!(
+        ordered < unordered,
+        "{ordered} nodes ordered, {unordered} unordered"
+    );
+}
+#[test]
+fn test_lazy_smp() {
+    use crate::utils::TestSearch;
+    let options = SearchOptions {
+        threads: 4,
+        ..Default::default()
+    };
+    let fen = "1k6/2pp4/1p2p3/4q3/1P2B3/2PK1R2/8/8 w - - 0 46";
+    let result = TestSearch::new(4).run(fen, "depth 7", options);
+    assert_eq!(result.best.unwrap().as_text(), "f3f8");
+}
+#[test]
+fn test_multi_pv() {
+    use crate::utils::TestSearch;
+    let lines = |fen: &str, searchmoves: &str| {
+        let options = SearchOptions {
+            multi_pv: 3,
+            ..Default::default()
+        };
+        let limits = format!("depth 4 {searchmoves}");
+        TestSearch::new(1).with_search(fen, &limits, options, |b, s| {
+            let best = s.iterate(b);
+            assert!(best.pv[0] == s.excluded[0]);
+            s.excluded.iter().map(|m| m.as_text()).collect::<Vec<_>>()
+        })
+    };
+    let startpos = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let moves = lines(startpos, "");
+    assert_eq!(moves.len(), 3);
+    assert!(moves
+        .iter()
+        .all(|m| moves.iter().filter(|n| *n == m).count() == 1));
+    let mut moves = lines(startpos, "searchmoves e2e4 d2d4");
+    moves.sort();
+    assert_eq!(moves, ["d2d4", "e2e4"]);
+    // Only two legal king moves
+    assert_eq!(lines("7k/8/8/5Q2/8/8/8/K7 b - - 0 1", "").len(), 2);
+}
+#[test]
+fn test_deterministic() {
+    use crate::utils::TestSearch;
+    let mut t = TestSearch::new(2);
+    let options = SearchOptions {
+        deterministic: true,
+        threads: 2,
+        ..Default::default()
+    };
+    let mut run = |fen: &str, limits: &str| {
+        let r = t.run(fen, limits, options);
+        (r.best.unwrap().as_text(), r.score, r.nodes)
+    };
+    let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let other = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+    let first = run(kiwipete, "nodes 20000 wtime 5 btime 5");
+    // Leaves the TT and history full of another position
+    run(other, "depth 6");
+    assert_eq!(run(kiwipete, "nodes 20000 wtime 100000 btime 5"), first);
+    assert_eq!(first.2, 20000);
+    let first = run(other, "depth 5");
+    run(kiwipete, "depth 5");
+    assert_eq!(run(other, "depth 5"), first);
+    // Only a clock, which doesn't count
+    let endgame = "8/8/4k3/8/2P5/4K3/8/8 w - - 0 1";
+    assert_eq!(
+        run(endgame, "wtime 1000 btime 1000"),
+        run(endgame, &format!("depth {DETERMINISTIC_DEPTH}"))
+    );
+}
+#[test]
+fn test_extension_budget() {
+    use crate::utils::TestSearch;
+    let mut t = TestSearch::new(1);
+    let mut nodes = |options: SearchOptions| {
+        let options = SearchOptions {
+            deterministic: true,
+            ..options
+        };
+        let fen = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+        t.run(fen, "depth 8", options).nodes
+    };
+    let all = SearchOptions {
+        recapture_extension: true,
+        passed_pawn_extension: true,
+        ..Default::default()
+    };
+    let none = SearchOptions {
+        check_extension: false,
+        singular_extension: false,
+        ..Default::default()
+    };
+    let no_budget = SearchOptions {
+        extension_budget: 0,
+        ..all
+    };
+    // Without a budget nothing gets extended, whatever is switched on
+    assert_eq!(nodes(no_budget), nodes(none));
+    assert_ne!(nodes(all), nodes(none));
+}
+#[test]
+fn test_no_legal_moves() {
+    use crate::utils::TestSearch;
+    let mut t = TestSearch::new(1);
+    // Stalemate, then mate
+    for fen in [
+        "k7/8/1Q6/8/8/8/8/7K b - - 0 1",
+        "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1",
+    ] {
+        let r = t.run(fen, "depth 5", SearchOptions::default());
+        assert!(r.best.is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    board::Board,
+    core_types::{Color, Piece, BB},
+    moves::{Move, MoveFlags},
+};
+
+pub const SEE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 20000];
+
+impl Board {
+    /// The piece taken by `m`, en passant included
+    pub fn captured_piece(&self, m: &Move) -> Option<Piece> {
+        if m.flags() == MoveFlags::EP {
+            return Some(Piece::Pawn);
+        }
+        if (m.get_to() & self.side[self.color.opposite() as usize]).empty() {
+            return None;
+        }
+        self.piece_on(m.get_to())
+    }
+    pub fn is_tactical(&self, m: &Move) -> bool {
+        m.is_promotion() || self.captured_piece(m).is_some()
+    }
+    /// Static exchange evaluation of `m` from the side to move's point of view
+    pub fn see(&self, m: &Move) -> i32 {
+        let from = m.get_from();
+        let to = m.get_to();
+        let mut occupied = self.side[0] | self.side[1];
+        let mut gain = [0; 32];
+        let mut d = 0;
+
+        let mut attacker_value = match self.piece_on(from) {
+            Some(p) => SEE_VALUES[p as usize],
+            None => return 0,
+        };
+        gain[0] = self.captured_piece(m).map_or(0, |p| SEE_VALUES[p as usize]);
+        if m.flags() == MoveFlags::EP {
+            let captured = match self.color {
+                Color::White => BB(to.0 >> 8),
+                Color::Black => BB(to.0 << 8),
+            };
+            occupied ^= captured;
+        }
+        if m.is_promotion() {
+            let promo = match m.flags() {
+                MoveFlags::PromoQueen => Piece::Queen,
+                MoveFlags::PromoRook => Piece::Rook,
+                MoveFlags::PromoBishop => Piece::Bishop,
+                _ => Piece::Knight,
+            };
+            attacker_value = SEE_VALUES[promo as usize];
+            gain[0] += attacker_value - SEE_VALUES[Piece::Pawn as usize];
+        }
+        occupied ^= from;
+        let mut color = self.color.opposite();
+
+        while d < gain.len() - 1 {
+            let attackers = self.attackers_to(to, occupied) & self.side[color as usize];
+            if attackers.empty() {
+                break;
+            }
+            let (piece, sq) = (0..6)
+                .map(Piece::from_u8)
+                .find_map(|p| {
+                    let bb = attackers & self.pieces[p as usize];
+                    (!bb.empty()).then(|| (p, BB(1 << bb.0.trailing_zeros())))
+                })
+                .expect("attackers is not empty");
+            d += 1;
+            gain[d] = attacker_value - gain[d - 1];
+            if (-gain[d - 1]).max(gain[d]) < 0 {
+                // Neither capturing nor standing pat changes the sign, stop here
+                d -= 1;
+                break;
+            }
+            attacker_value = SEE_VALUES[piece as usize];
+            occupied ^= sq;
+            color = color.opposite();
+        }
+        while d > 0 {
+            gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+            d -= 1;
+        }
+        gain[0]
+    }
+}
+
+#[test]
+fn test_see() {
+    use crate::fen::load_fen;
+    crate::attacks::init_magics(false);
+    let find = |b: &Board, text: &str| {
+        b.gen_pseudo_legal()
+            .into_iter()
+            .find(|m| m.as_text() == text)
+            .unwrap()
+    };
+    let mut b = Board::new();
+    load_fen(&mut b, "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1").unwrap();
+    assert_eq!(b.see(&find(&b, "e1e5")), 100);
+    load_fen(
+        &mut b,
+        "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+    )
+    .unwrap();
+    assert_eq!(b.see(&find(&b, "d3e5")), 100 - 320);
+    // Defended only by a pawn, queen takes rook
+    load_fen(&mut b, "4k3/8/2p5/3r4/8/8/3Q4/4K3 w - - 0 1").unwrap();
+    assert_eq!(b.see(&find(&b, "d2d5")), 500 - 900);
+    // Quiet move onto an attacked square
+    assert_eq!(b.see(&find(&b, "d2d4")), -900);
+}
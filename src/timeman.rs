@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use crate::{core_types::Color, limits::SearchLimits, search::SearchParams};
+
+pub const DEFAULT_MOVE_OVERHEAD: u64 = 30;
+pub const MAX_MOVE_OVERHEAD: u64 = 5000;
+
+/// Decides when to stop thinking.
+///
+/// The soft limit is checked between iterations and moves with how stable the
+/// search looks, the hard limit aborts a running iteration.
+pub struct TimeManager {
+    timer: Instant,
+    /// Planned time for this move, before any adjustment
+    base: Option<Duration>,
+    soft: Option<Duration>,
+    hard: Option<Duration>,
+    /// Grows each time the best move changes, decays every iteration
+    instability: f64,
+    params: SearchParams,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits, color: Color, overhead: u64, params: &SearchParams) -> Self {
+        let (base, hard) = Self::allocate(limits, color, overhead, params);
+        TimeManager {
+            timer: Instant::now(),
+            base,
+            soft: base,
+            hard,
+            instability: 0.,
+            params: *params,
+        }
+    }
+
+    /// Returns the planned time for this move and the hard limit
+    fn allocate(
+        limits: &SearchLimits,
+        color: Color,
+        overhead: u64,
+        p: &SearchParams,
+    ) -> (Option<Duration>, Option<Duration>) {
+        if limits.infinite {
+            return (None, None);
+        }
+        let ms = Duration::from_millis;
+        if let Some(movetime) = limits.movetime {
+            let t = ms(movetime.saturating_sub(overhead).max(1));
+            return (Some(t), Some(t));
+        }
+        let Some(time) = limits.time[color as usize] else {
+            return (None, None);
+        };
+        // Moves left in the game we plan for in sudden death
+        let moves_left = p.tm_moves.max(1) as u64;
+        let inc = limits.inc[color as usize] * p.tm_inc_percent.max(0) as u64 / 100;
+        let safe = time.saturating_sub(overhead).max(1);
+        let (base, max) = match limits.movestogo {
+            // Classical control, the clock gets refilled after `movestogo` moves
+            Some(moves) => {
+                let moves = (moves as u64).clamp(1, 50);
+                let max = if moves == 1 {
+                    safe * 9 / 10
+                } else {
+                    safe * 7 / 10
+                };
+                (safe / moves + inc, max)
+            }
+            // Sudden death, with or without increment
+            None => (safe / moves_left + inc, safe / 2),
+        };
+        let base = base.min(max);
+        let hard = (base * p.tm_hard_factor.max(1) as u64).min(max);
+        (Some(ms(base.max(1))), Some(ms(hard.max(1))))
+    }
+
+    /// Restarts the clock, on `ponderhit`
+    pub fn restart(&mut self) {
+        self.timer = Instant::now();
+    }
+
+    pub fn timed(&self) -> bool {
+        self.hard.is_some()
+    }
+
+    /// Don't start another iteration
+    pub fn soft_limit_reached(&self) -> bool {
+        self.soft.is_some_and(|soft| self.timer.elapsed() >= soft)
+    }
+
+    /// Abort the current iteration
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard.is_some_and(|hard| self.timer.elapsed() >= hard)
+    }
+
+    /// Stretches the soft limit after every iteration: an unstable best move or
+    /// a falling score earn more time, a steady search gives some back
+    pub fn update(&mut self, best_changed: bool, score: i32, prev_score: Option<i32>) {
+        let (Some(base), Some(hard)) = (self.base, self.hard) else {
+            return;
+        };
+        let p = &self.params;
+        let percent = |n: i32| n as f64 / 100.;
+        self.instability *= percent(p.tm_instability_decay);
+        if best_changed {
+            self.instability += 1.;
+        }
+        let stability = percent(p.tm_stability) + percent(p.tm_instability) * self.instability;
+        let drop = prev_score.map_or(0, |prev| prev - score).clamp(0, 100);
+        let falling = 1. + drop as f64 / p.tm_drop_divisor.max(1) as f64;
+        let (min, max) = (percent(p.tm_min_scale), percent(p.tm_max_scale));
+        let soft = base.mul_f64((stability * falling).clamp(min, max.max(min)));
+        self.soft = Some(soft.min(hard));
+    }
+}
+
+#[test]
+fn test_allocation() {
+    let ms = Duration::from_millis;
+    let mut limits = SearchLimits {
+        movetime: Some(1000),
+        ..Default::default()
+    };
+    let tm = TimeManager::new(&limits, Color::White, 30, &SearchParams::DEFAULT);
+    assert_eq!((tm.soft, tm.hard), (Some(ms(970)), Some(ms(970))));
+
+    limits.movetime = None;
+    limits.time = [Some(60_000), Some(10)];
+    limits.inc = [1000, 0];
+    let tm = TimeManager::new(&limits, Color::White, 30, &SearchParams::DEFAULT);
+    assert_eq!(tm.soft, Some(ms(59_970 / 30 + 750)));
+    assert_eq!(tm.hard, Some(ms((59_970 / 30 + 750) * 4)));
+    // Almost flagging, still never use more than half the clock
+    let tm = TimeManager::new(&limits, Color::Black, 30, &SearchParams::DEFAULT);
+    assert_eq!(tm.hard, Some(ms(1)));
+
+    limits.movestogo = Some(1);
+    let tm = TimeManager::new(&limits, Color::White, 30, &SearchParams::DEFAULT);
+    assert_eq!(tm.hard, Some(ms(59_970 * 9 / 10)));
+
+    limits.infinite = true;
+    assert!(!TimeManager::new(&limits, Color::White, 30, &SearchParams::DEFAULT).timed());
+}
+#[test]
+fn test_update() {
+    let limits = SearchLimits {
+        time: [Some(60_000), Some(60_000)],
+        ..Default::default()
+    };
+    let mut tm = TimeManager::new(&limits, Color::White, 0, &SearchParams::DEFAULT);
+    let base = tm.soft.unwrap();
+    tm.update(false, 20, Some(20));
+    assert!(tm.soft.unwrap() < base);
+    tm.update(true, -50, Some(20));
+    assert!(tm.soft.unwrap() > base);
+    assert!(tm.soft <= tm.hard);
+}
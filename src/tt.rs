@@ -0,0 +1,206 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::{moves::Move, search::MATE_BOUND};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 65536;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Bound {
+    Exact = 1,
+    Lower = 2,
+    Upper = 3,
+}
+
+#[derive(Clone, Copy)]
+pub struct TTEntry {
+    pub mv: Move,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    age: u8,
+}
+
+/// Layout of the data word:
+///   Age   Bound  Depth  Score   Move
+///   6b     2b     8b     16b    16b
+impl TTEntry {
+    fn pack(&self) -> u64 {
+        self.mv.data as u64
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth as u64) << 32
+            | (self.bound as u64) << 40
+            | ((self.age & 0b111111) as u64) << 42
+    }
+    fn unpack(data: u64) -> Option<Self> {
+        let bound = match data >> 40 & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        Some(TTEntry {
+            mv: Move { data: data as u16 },
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: (data >> 32) as u8,
+            bound,
+            age: (data >> 42 & 0b111111) as u8,
+        })
+    }
+}
+
+/// The key is stored xored with the data, so a torn write from another
+/// thread just looks like a miss instead of a wrong entry.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ data;
+        (key, data)
+    }
+    fn store(&self, key: u64, data: u64) {
+        self.key.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// Every bucket has a depth preferred slot and an always replace slot
+const BUCKET: usize = 2;
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    age: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(mb: usize) -> Self {
+        let mut tt = TranspositionTable {
+            slots: Vec::new(),
+            age: AtomicU8::new(0),
+        };
+        tt.resize(mb);
+        tt
+    }
+    pub fn resize(&mut self, mb: usize) {
+        let mb = mb.clamp(1, MAX_HASH_MB);
+        let count = mb * 1024 * 1024 / std::mem::size_of::<Slot>();
+        let count = count / BUCKET * BUCKET;
+        self.slots = (0..count).map(|_| Slot::default()).collect();
+    }
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.store(0, 0);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+    /// Called once per `go`, so entries from earlier searches get replaced first
+    pub fn new_search(&self) {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store((age + 1) & 0b111111, Ordering::Relaxed);
+    }
+    fn bucket(&self, key: u64) -> &[Slot] {
+        let buckets = self.slots.len() / BUCKET;
+        let idx = ((key as u128 * buckets as u128) >> 64) as usize * BUCKET;
+        &self.slots[idx..idx + BUCKET]
+    }
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        for slot in self.bucket(key) {
+            let (k, data) = slot.load();
+            if k == key {
+                if let Some(entry) = TTEntry::unpack(data) {
+                    return Some(entry);
+                }
+            }
+        }
+        None
+    }
+    pub fn store(&self, key: u64, mv: Move, score: i32, depth: u8, bound: Bound) {
+        let age = self.age.load(Ordering::Relaxed);
+        let bucket = self.bucket(key);
+        let (old_key, old_data) = bucket[0].load();
+        let old = TTEntry::unpack(old_data);
+        let mut entry = TTEntry {
+            mv,
+            score,
+            depth,
+            bound,
+            age,
+        };
+        if old_key == key && mv == Move::default() {
+            // Don't lose the best move of the position on an upper bound
+            if let Some(old) = old {
+                entry.mv = old.mv;
+            }
+        }
+        let replace_deep = match old {
+            None => true,
+            Some(old) => old_key == key || old.age != age || depth >= old.depth,
+        };
+        if replace_deep {
+            bucket[0].store(key, entry.pack());
+        } else {
+            bucket[1].store(key, entry.pack());
+        }
+    }
+    /// Permille of the table filled during the current search
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        self.slots
+            .iter()
+            .take(1000)
+            .filter(|s| TTEntry::unpack(s.load().1).is_some_and(|e| e.age == age))
+            .count()
+            * 1000
+            / self.slots.len().min(1000)
+    }
+}
+
+/// Mate scores are stored relative to the node, not the root
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[test]
+fn test_tt_store_probe() {
+    use crate::{core_types::SquareIdx, moves::MoveFlags};
+    let tt = TranspositionTable::new(1);
+    let m = Move::new(SquareIdx(12), SquareIdx(28), MoveFlags::PawnDoublePush);
+    tt.store(0xdeadbeef, m, -1234, 7, Bound::Lower);
+    let e = tt.probe(0xdeadbeef).unwrap();
+    assert!(e.mv == m);
+    assert_eq!(e.score, -1234);
+    assert_eq!(e.depth, 7);
+    assert_eq!(e.bound, Bound::Lower);
+    assert!(tt.probe(0xdeadbeef ^ 1).is_none());
+    assert!(tt.hashfull() <= 1000);
+    tt.clear();
+    assert!(tt.probe(0xdeadbeef).is_none());
+}
+#[test]
+fn test_tt_mate_scores() {
+    use crate::search::MATE;
+    let s = MATE - 5;
+    assert_eq!(score_from_tt(score_to_tt(s, 3), 3), s);
+    assert_eq!(score_to_tt(s, 3), MATE - 2);
+    assert_eq!(score_from_tt(score_to_tt(-s, 4), 4), -s);
+}
@@ -0,0 +1,164 @@
+use std::{fs, thread, time::Instant};
+
+use crate::{board::Board, fen::load_fen, weights::Weights};
+
+/// Local search gives up after this many passes over all the parameters
+const MAX_PASSES: usize = 100;
+
+/// Texel tuning: finds the weights that best predict the game results of
+/// `data` and writes them to `output` after every pass, in the format the
+/// `WeightsFile` option loads.
+///
+/// Every line of `data` is a FEN followed by the result from White's side,
+/// `1-0`, `0-1` and `1/2-1/2`, quoted or not, or `[1.0]`, `[0.5]` and
+/// `[0.0]`. The positions should be quiet, they are evaluated as they are.
+pub fn tune(data: &str, output: &str) {
+    let text = match fs::read_to_string(data) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("can't read {data}: {e}");
+            return;
+        }
+    };
+    let positions = text.lines().filter_map(parse_line).collect::<Vec<_>>();
+    println!("{} positions", positions.len());
+    if positions.is_empty() {
+        return;
+    }
+
+    let mut w = Weights::DEFAULT;
+    let evals = evaluate_all(&positions, &w);
+    let k = find_k(&evals, &positions);
+    let mut best = error(&evals, &positions, k);
+    println!("K {k:.4}, error {best:.6}");
+
+    let start = Instant::now();
+    let count = w.params().len();
+    for pass in 1..=MAX_PASSES {
+        let mut improved = false;
+        for i in 0..count {
+            for delta in [1, -1] {
+                *w.params()[i].1 += delta;
+                let e = error(&evaluate_all(&positions, &w), &positions, k);
+                if e < best {
+                    best = e;
+                    improved = true;
+                    break;
+                }
+                *w.params()[i].1 -= delta;
+            }
+        }
+        println!(
+            "pass {pass}: error {best:.6}, {}s",
+            start.elapsed().as_secs()
+        );
+        let text = format!(
+            "# Tuned on {data} to an error of {best:.6}\n{}",
+            w.to_text()
+        );
+        if let Err(e) = fs::write(output, text) {
+            eprintln!("can't write {output}: {e}");
+            return;
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+/// A position and its game result for White, 1 for a win, 0.5 for a draw
+fn parse_line(line: &str) -> Option<(Board, f64)> {
+    let results = [
+        ("1/2-1/2", 0.5),
+        ("1-0", 1.0),
+        ("0-1", 0.0),
+        ("[0.5]", 0.5),
+        ("[1.0]", 1.0),
+        ("[0.0]", 0.0),
+    ];
+    let (token, result) = results.into_iter().find(|(t, _)| line.contains(t))?;
+    let fen = &line[..line.find(token)?];
+    let mut b = Board::new();
+    load_fen(&mut b, fen).ok()?;
+    Some((b, result))
+}
+
+/// Static evaluations from White's side, spread over all the cores
+fn evaluate_all(positions: &[(Board, f64)], weights: &Weights) -> Vec<i32> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = positions.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let handles = positions
+            .chunks(chunk)
+            .map(|chunk| {
+                scope.spawn(|| {
+                    chunk
+                        .iter()
+                        .map(|(b, _)| b.trace(weights, true).score())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("tuning thread panicked"))
+            .collect()
+    })
+}
+
+/// Expected score for White given the eval
+fn sigmoid(eval: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval as f64 / 400.0))
+}
+
+/// Mean squared difference between the results and what the evals predict
+fn error(evals: &[i32], positions: &[(Board, f64)], k: f64) -> f64 {
+    let total = evals
+        .iter()
+        .zip(positions)
+        .map(|(&eval, (_, result))| (result - sigmoid(eval, k)).powi(2))
+        .sum::<f64>();
+    total / positions.len() as f64
+}
+
+/// The scaling of the sigmoid that fits the current evals best, by ternary
+/// search since the error is convex in it
+fn find_k(evals: &[i32], positions: &[(Board, f64)]) -> f64 {
+    let (mut low, mut high) = (0.0, 5.0);
+    for _ in 0..100 {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if error(evals, positions, a) < error(evals, positions, b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[test]
+fn test_texel_error() {
+    crate::attacks::init_magics(false);
+    let lines = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 [0.5]",
+        "4k3/8/8/8/8/8/8/QQQ1K3 w - - 0 1 c9 \"1-0\";",
+        "4k3/8/8/8/8/8/8/q2K4 b - - 0 1; 0-1",
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1 1/2-1/2",
+        "not a position [1.0]",
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+    ];
+    let positions = lines
+        .iter()
+        .filter_map(|l| parse_line(l))
+        .collect::<Vec<_>>();
+    let results = positions.iter().map(|(_, r)| *r).collect::<Vec<_>>();
+    assert_eq!(results, [0.5, 1.0, 0.0, 0.5]);
+
+    // Winning side way ahead, so the bigger K the better, up to the limit
+    let evals = evaluate_all(&positions, &Weights::DEFAULT);
+    let k = find_k(&evals, &positions);
+    assert!(k > 4.0);
+    assert!(error(&evals, &positions, k) < error(&evals, &positions, 1.0));
+    assert!(error(&evals, &positions, k) < 0.01);
+}
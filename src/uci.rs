@@ -2,26 +2,195 @@ use std::{
     collections::HashMap,
     env,
     io::stdin,
-    time::{Duration, Instant},
+    sync::{atomic::Ordering, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Instant,
 };
 
+#[cfg(feature = "tune")]
+use crate::params;
 use crate::{
+    bench::{bench, DEFAULT_BENCH_DEPTH},
     board::Board,
-    core_types::Color::{Black, White},
+    core_types::Color,
     fen::load_fen,
-    search::Search,
+    limits::SearchLimits,
+    nnue::{Accumulator, Network},
+    ordering::History,
+    search::{
+        Search, SearchOptions, Signals, DEFAULT_EXTENSION_BUDGET, DEFAULT_THREADS,
+        MAX_EXTENSION_BUDGET, MAX_MULTI_PV, MAX_THREADS,
+    },
+    timeman::{DEFAULT_MOVE_OVERHEAD, MAX_MOVE_OVERHEAD},
+    tt::{TranspositionTable, DEFAULT_HASH_MB, MAX_HASH_MB},
+    weights::Weights,
 };
 pub struct Game {
     pub b: Option<Board>,
+    pub tt: Arc<TranspositionTable>,
+    pub options: SearchOptions,
+    signals: Arc<Signals>,
+    /// One per search thread
+    histories: Arc<Mutex<Vec<History>>>,
+    /// Set through `EvalFile`, the handcrafted eval is used without one
+    net: Option<Arc<Network>>,
+    /// Of the handcrafted eval, set through `WeightsFile` and with the `tune`
+    /// feature one by one
+    weights: Arc<Weights>,
+    search_thread: Option<JoinHandle<()>>,
 }
 impl Game {
+    pub fn new() -> Self {
+        Game {
+            b: None,
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            options: SearchOptions::default(),
+            signals: Arc::new(Signals::default()),
+            histories: Arc::new(Mutex::new(Vec::new())),
+            net: None,
+            weights: Arc::new(Weights::DEFAULT),
+            search_thread: None,
+        }
+    }
+    /// Runs the search on a worker thread, which prints `bestmove` when done
+    fn start_search(&mut self, b: Board, limits: SearchLimits) {
+        self.stop_search();
+        self.signals.stop.store(false, Ordering::Relaxed);
+        self.signals.ponder.store(limits.ponder, Ordering::Relaxed);
+        let tt = self.tt.clone();
+        let signals = self.signals.clone();
+        let options = self.options;
+        let histories = self.histories.clone();
+        let net = self.net.clone();
+        let weights = self.weights.clone();
+        self.search_thread = Some(thread::spawn(move || {
+            let mut histories = histories.lock().expect("only one search runs at a time");
+            histories.resize_with(options.threads, History::new);
+            let net = net.as_deref();
+            let histories = &mut histories;
+            let result =
+                Search::search(limits, b, &tt, &signals, options, histories, net, &weights);
+            // The null move when mated or stalemated
+            let Some(best) = result.best else {
+                println!("bestmove 0000");
+                return;
+            };
+            match result.ponder {
+                Some(p) => println!("bestmove {} ponder {}", best.as_text(), p.as_text()),
+                None => println!("bestmove {}", best.as_text()),
+            }
+        }));
+    }
+    /// Stops the running search, if any, and waits for it to print `bestmove`
+    fn stop_search(&mut self) {
+        self.signals.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.search_thread.take() {
+            handle.join().expect("search thread panicked");
+        }
+    }
+    /// One of the tuning parameters, false if `name` isn't one
+    #[cfg(feature = "tune")]
+    fn set_param(&mut self, name: &str, value: &str) -> bool {
+        let weights = Arc::make_mut(&mut self.weights);
+        params::set_param(name, value, &mut self.options.params, weights)
+    }
+    /// `setoption name <name> value <value>`
+    fn set_option(&mut self, args: &str) {
+        self.stop_search();
+        let mut words = args.split_whitespace();
+        if words.next() != Some("name") {
+            return;
+        }
+        let name = words
+            .by_ref()
+            .take_while(|w| *w != "value")
+            .collect::<Vec<_>>();
+        let value = words.collect::<Vec<_>>().join(" ");
+        match name.join(" ").to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => Arc::get_mut(&mut self.tt)
+                    .expect("no search is running")
+                    .resize(mb),
+                Err(_) => eprintln!("Invalid Hash value: {value}"),
+            },
+            "move overhead" => match value.parse::<u64>() {
+                Ok(ms) => self.options.move_overhead = ms.min(MAX_MOVE_OVERHEAD),
+                Err(_) => eprintln!("Invalid Move Overhead value: {value}"),
+            },
+            "threads" => match value.parse::<usize>() {
+                Ok(n) => self.options.threads = n.clamp(1, MAX_THREADS),
+                Err(_) => eprintln!("Invalid Threads value: {value}"),
+            },
+            "multipv" => match value.parse::<usize>() {
+                Ok(n) => self.options.multi_pv = n.clamp(1, MAX_MULTI_PV),
+                Err(_) => eprintln!("Invalid MultiPV value: {value}"),
+            },
+            "extension budget" => match value.parse::<u8>() {
+                Ok(plies) => self.options.extension_budget = plies.min(MAX_EXTENSION_BUDGET),
+                Err(_) => eprintln!("Invalid Extension Budget value: {value}"),
+            },
+            // Pondering is driven by `go ponder`, nothing to set up
+            "ponder" => (),
+            "evalfile" => {
+                if value.is_empty() || value == "<empty>" {
+                    self.net = None;
+                    return;
+                }
+                match Network::load(&value) {
+                    Ok(net) => {
+                        println!("info string loaded network {value}");
+                        self.net = Some(Arc::new(net));
+                    }
+                    Err(e) => {
+                        println!("info string {e}, using the handcrafted eval");
+                        self.net = None;
+                    }
+                }
+            }
+            "weightsfile" => {
+                if value.is_empty() || value == "<empty>" {
+                    self.weights = Arc::new(Weights::DEFAULT);
+                    return;
+                }
+                match Weights::load(&value) {
+                    Ok(w) => {
+                        println!("info string loaded weights {value}");
+                        self.weights = Arc::new(w);
+                    }
+                    Err(e) => println!("info string {e}, keeping the current weights"),
+                }
+            }
+            #[cfg(feature = "tune")]
+            name if self.set_param(name, &value) => (),
+            name => {
+                let switch = self
+                    .options
+                    .switches()
+                    .into_iter()
+                    .find(|(n, _)| n.to_lowercase() == name);
+                match switch {
+                    Some((_, on)) => *on = value == "true",
+                    None => eprintln!("Unknown option: {name}"),
+                }
+            }
+        }
+    }
     pub fn uci_loop(&mut self) {
         loop {
             let mut command = String::new();
 
-            stdin().read_line(&mut command).unwrap();
+            if stdin().read_line(&mut command).unwrap() == 0 {
+                // stdin closed, the GUI is gone
+                self.stop_search();
+                return;
+            }
             command = command.trim().to_owned();
 
+            if let Some(args) = command.strip_prefix("setoption") {
+                self.set_option(args);
+                continue;
+            }
+
             let mut cmd = HashMap::<String, String>::new();
             let mut munch = "".to_owned();
             let mut fen_munching = false;
@@ -45,14 +214,90 @@ impl Game {
                 if word == "uci" {
                     println!("id name the Rust Procrastination");
                     println!("id author Andrii Dokhniak");
+                    println!(
+                        "option name Hash type spin default {DEFAULT_HASH_MB} min 1 max {MAX_HASH_MB}"
+                    );
+                    println!(
+                        "option name Move Overhead type spin default {DEFAULT_MOVE_OVERHEAD} min 0 max {MAX_MOVE_OVERHEAD}"
+                    );
+                    println!(
+                        "option name Threads type spin default {DEFAULT_THREADS} min 1 max {MAX_THREADS}"
+                    );
+                    println!("option name MultiPV type spin default 1 min 1 max {MAX_MULTI_PV}");
+                    println!(
+                        "option name Extension Budget type spin default {DEFAULT_EXTENSION_BUDGET} min 0 max {MAX_EXTENSION_BUDGET}"
+                    );
+                    println!("option name Ponder type check default false");
+                    println!("option name EvalFile type string default <empty>");
+                    println!("option name WeightsFile type string default <empty>");
+                    for (name, on) in SearchOptions::default().switches() {
+                        println!("option name {name} type check default {on}");
+                    }
+                    #[cfg(feature = "tune")]
+                    params::print_options();
                     println!("uciok");
                 } else if word == "isready" {
                     println!("readyok");
+                } else if word == "ucinewgame" {
+                    self.stop_search();
+                    self.tt.clear();
+                    for history in self.histories.lock().unwrap().iter_mut() {
+                        history.clear();
+                    }
+                } else if word == "stop" {
+                    self.stop_search();
+                } else if word == "ponderhit" {
+                    self.signals.ponder.store(false, Ordering::Relaxed);
+                } else if word == "params" {
+                    #[cfg(feature = "tune")]
+                    params::print_spsa(&self.options.params, &self.weights);
+                    #[cfg(not(feature = "tune"))]
+                    eprintln!("parameters are only exposed with the tune feature");
+                } else if word == "trace" {
+                    match self.b {
+                        Some(b) => b.trace(&self.weights, self.options.king_safety).print(),
+                        None => eprintln!("trace without a position"),
+                    }
+                } else if word == "eval" {
+                    match self.b {
+                        Some(b) => {
+                            b.eprint_board();
+                            print!(
+                                "{}",
+                                b.trace(&self.weights, self.options.king_safety).table()
+                            );
+                            if let Some(net) = &self.net {
+                                let eval = net.evaluate(&Accumulator::new(net, &b), b.color);
+                                let eval = match b.color {
+                                    Color::White => eval,
+                                    Color::Black => -eval,
+                                };
+                                println!("NNUE evaluation: {eval} (White side)");
+                            }
+                        }
+                        None => eprintln!("eval without a position"),
+                    }
+                } else if word == "bench" {
+                    self.stop_search();
+                    let depth = command
+                        .split_whitespace()
+                        .nth(1)
+                        .and_then(|d| d.parse().ok());
+                    bench(
+                        depth.unwrap_or(DEFAULT_BENCH_DEPTH),
+                        self.options,
+                        self.net.as_deref(),
+                        &self.weights,
+                    );
+                    break;
                 } else if command == "test" {
                     let mut b = Board::new();
                     load_fen(&mut b, "1k6/2pp4/1p2p3/4q3/1P2B3/2PK1R2/8/8 w - - 0 46").unwrap();
-                    let res = Search::search(Duration::from_secs(2), b);
-                    println!("bestmove {}", res.as_text());
+                    let limits = SearchLimits {
+                        movetime: Some(2000),
+                        ..Default::default()
+                    };
+                    self.start_search(b, limits);
                 } else if word == "position" {
                     cmd.insert("position".to_owned(), "true".to_owned());
                 } else if word == "startpos" {
@@ -67,6 +312,7 @@ impl Game {
                     go_munching = true;
                     munching = true;
                 } else if word == "quit" {
+                    self.stop_search();
                     return;
                 }
             }
@@ -77,12 +323,8 @@ impl Game {
                 cmd.insert("fen".to_owned(), munch.trim().to_owned());
             }
             if go_munching {
-                cmd.insert("go".to_owned(), "true".to_owned());
+                cmd.insert("go".to_owned(), munch.trim().to_owned());
                 eprintln!("munch: {munch}");
-                let mut i = munch.split_whitespace();
-                while let (Some(k), Some(v)) = (i.next(), i.next()) {
-                    cmd.insert(k.to_owned(), v.to_owned());
-                }
             }
             if cmd.get("position").is_some() {
                 let mut b = Board::new();
@@ -100,22 +342,13 @@ impl Game {
                 }
                 self.b = Some(b);
             }
-            if cmd.get("go").is_some() {
-                let color = self.b.unwrap().color;
-                let time: i32 = match color {
-                    White => cmd.get("wtime").unwrap().parse().unwrap(),
-                    Black => cmd.get("btime").unwrap().parse().unwrap(),
-                };
-                let time = time.abs(); // in case the time is negative
-                let time = time.abs();
-                let inc: i32 = match color {
-                    White => cmd.get("winc").unwrap_or(&"0".to_owned()).parse().unwrap(),
-                    Black => cmd.get("binc").unwrap_or(&"0".to_owned()).parse().unwrap(),
+            if let Some(args) = cmd.get("go") {
+                let Some(b) = self.b else {
+                    eprintln!("go without a position");
+                    continue;
                 };
-                assert!(inc >= 0);
-                let tc = time / 20 + inc / 2;
-                let s = Search::search(Duration::from_millis(tc as u64), self.b.unwrap());
-                println!("bestmove {}", s.as_text());
+                let limits = SearchLimits::parse(args, &b);
+                self.start_search(b, limits);
             }
         }
     }
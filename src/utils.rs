@@ -146,3 +146,104 @@ impl Board {
         eprintln!();
     }
 }
+
+/// For tests of state kept up to date move by move: plays every line `depth`
+/// plies deep from a position where king moves, castling, en passant,
+/// promotions and captures all show up. `start` makes the state of that
+/// position, `step` gets every move as the boards before and after it and the
+/// state before, checks what it wants and returns the state after.
+#[cfg(test)]
+pub fn walk_moves<T: Copy>(
+    depth: u8,
+    start: impl FnOnce(&Board) -> T,
+    step: &mut impl FnMut(&Board, &Board, T) -> T,
+) {
+    fn walk<T: Copy>(
+        b: &Board,
+        state: T,
+        depth: u8,
+        step: &mut impl FnMut(&Board, &Board, T) -> T,
+    ) {
+        if depth == 0 {
+            return;
+        }
+        for m in b.gen_pseudo_legal() {
+            let mut new_b = *b;
+            if new_b.make_move(&m) {
+                let new_state = step(b, &new_b, state);
+                walk(&new_b, new_state, depth - 1, step);
+            }
+        }
+    }
+    crate::attacks::init_magics(false);
+    let mut b = Board::new();
+    crate::fen::load_fen(
+        &mut b,
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    )
+    .unwrap();
+    let state = start(&b);
+    walk(&b, state, depth, step);
+}
+
+/// What the UCI loop keeps around searches: a TT, the signals and one history
+/// per thread, shared by every search a test runs
+#[cfg(test)]
+pub struct TestSearch {
+    pub tt: crate::tt::TranspositionTable,
+    pub signals: crate::search::Signals,
+    pub histories: Vec<crate::ordering::History>,
+}
+
+#[cfg(test)]
+impl TestSearch {
+    pub fn new(threads: usize) -> Self {
+        crate::attacks::init_magics(false);
+        TestSearch {
+            tt: crate::tt::TranspositionTable::new(16),
+            signals: crate::search::Signals::default(),
+            histories: (0..threads)
+                .map(|_| crate::ordering::History::new())
+                .collect(),
+        }
+    }
+
+    fn board(fen: &str) -> Board {
+        let mut b = Board::new();
+        crate::fen::load_fen(&mut b, fen).unwrap();
+        b
+    }
+
+    /// Searches `fen` like `go` with `limits` would
+    pub fn run(
+        &mut self,
+        fen: &str,
+        limits: &str,
+        options: crate::search::SearchOptions,
+    ) -> crate::search::SearchResult {
+        let b = TestSearch::board(fen);
+        let limits = crate::limits::SearchLimits::parse(limits, &b);
+        let (tt, signals, histories) = (&self.tt, &self.signals, &mut self.histories);
+        let weights = &crate::weights::Weights::DEFAULT;
+        crate::search::Search::search(limits, b, tt, signals, options, histories, None, weights)
+    }
+
+    /// Hands a single threaded `Search` set up on `fen` to `f`, which drives
+    /// it itself
+    pub fn with_search<R>(
+        &mut self,
+        fen: &str,
+        limits: &str,
+        options: crate::search::SearchOptions,
+        f: impl FnOnce(&Board, &mut crate::search::Search) -> R,
+    ) -> R {
+        use crate::search::{Search, Shared};
+        let b = TestSearch::board(fen);
+        let limits = crate::limits::SearchLimits::parse(limits, &b);
+        let shared = Shared::new(1, None, &crate::weights::Weights::DEFAULT);
+        let tm = crate::timeman::TimeManager::new(&limits, b.color, 0, &options.params);
+        let (tt, signals, history) = (&self.tt, &self.signals, &mut self.histories[0]);
+        let mut s = Search::new(0, limits, tm, tt, signals, &shared, options, history);
+        f(&b, &mut s)
+    }
+}
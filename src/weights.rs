@@ -0,0 +1,137 @@
+use std::fs;
+
+use crate::{
+    endgame::EndgameWeights,
+    eval::{PsqtWeights, S},
+    king_safety::KingWeights,
+    pawns::PawnWeights,
+    pieces::PieceWeights,
+};
+
+/// Every weight of the handcrafted evaluation, so they can be changed at
+/// runtime by the tuner. Passed down to everything that evaluates.
+#[derive(Clone, Copy, Debug)]
+pub struct Weights {
+    pub psqt: PsqtWeights,
+    pub pawns: PawnWeights,
+    pub king: KingWeights,
+    pub pieces: PieceWeights,
+    pub endgame: EndgameWeights,
+}
+
+impl Weights {
+    pub const DEFAULT: Weights = Weights {
+        psqt: PsqtWeights::DEFAULT,
+        pawns: PawnWeights::DEFAULT,
+        king: KingWeights::DEFAULT,
+        pieces: PieceWeights::DEFAULT,
+        endgame: EndgameWeights::DEFAULT,
+    };
+
+    /// Every single number by its name, `pawns.passed[4].eg` and the like
+    pub fn params(&mut self) -> Vec<(String, &mut i32)> {
+        let mut params = Vec::new();
+        self.psqt.params("psqt.", &mut params);
+        self.pawns.params("pawns.", &mut params);
+        self.king.params("king.", &mut params);
+        self.pieces.params("pieces.", &mut params);
+        self.endgame.params("endgame.", &mut params);
+        params
+    }
+
+    /// One `name value` line per weight, what `load` reads back
+    pub fn to_text(mut self) -> String {
+        self.params()
+            .into_iter()
+            .map(|(name, value)| format!("{name} {value}\n"))
+            .collect()
+    }
+
+    /// Weights from the text written by `to_text`, like the tuner output.
+    /// Lines starting with `#` are comments and weights that aren't listed
+    /// keep their default.
+    pub fn from_text(text: &str) -> Result<Weights, String> {
+        let mut w = Weights::DEFAULT;
+        let mut params = w.params();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("expected a name and a value: {line}"))?;
+            let param = params
+                .iter_mut()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| format!("unknown weight {name}"))?;
+            *param.1 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid value for {name}: {value}"))?;
+        }
+        drop(params);
+        Ok(w)
+    }
+
+    pub fn load(path: &str) -> Result<Weights, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {path}: {e}"))?;
+        Weights::from_text(&text)
+    }
+}
+
+/// A weight made of plain numbers that can be listed one by one
+pub trait Param {
+    fn visit<'a>(&'a mut self, name: String, params: &mut Vec<(String, &'a mut i32)>);
+}
+
+impl Param for i32 {
+    fn visit<'a>(&'a mut self, name: String, params: &mut Vec<(String, &'a mut i32)>) {
+        params.push((name, self));
+    }
+}
+
+impl Param for S {
+    fn visit<'a>(&'a mut self, name: String, params: &mut Vec<(String, &'a mut i32)>) {
+        params.push((format!("{name}.mg"), &mut self.0));
+        params.push((format!("{name}.eg"), &mut self.1));
+    }
+}
+
+impl<T: Param, const N: usize> Param for [T; N] {
+    fn visit<'a>(&'a mut self, name: String, params: &mut Vec<(String, &'a mut i32)>) {
+        for (i, param) in self.iter_mut().enumerate() {
+            param.visit(format!("{name}[{i}]"), params);
+        }
+    }
+}
+
+#[test]
+fn test_params() {
+    let mut w = Weights::DEFAULT;
+    let mut params = w.params();
+    let mut names = params.iter().map(|(n, _)| n.clone()).collect::<Vec<_>>();
+    assert!(names.contains(&"pawns.passed[4].eg".to_owned()));
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), params.len());
+
+    *params[0].1 += 1;
+    assert_eq!(w.psqt.mg_values[0], Weights::DEFAULT.psqt.mg_values[0] + 1);
+
+    // What the tuner writes loads back the same
+    let mut changed = Weights::DEFAULT;
+    changed.king.danger[10] += 7;
+    changed.endgame.opposite_bishops -= 3;
+    let text = format!("# comment\n{}", changed.to_text());
+    let loaded = Weights::from_text(&text).unwrap();
+    assert_eq!(loaded.to_text(), changed.to_text());
+    assert_eq!(
+        Weights::from_text("pieces.mobility_base[2] 5")
+            .unwrap()
+            .pieces
+            .mobility_base[2],
+        5
+    );
+    assert!(Weights::from_text("nothing 1").is_err());
+    assert!(Weights::from_text("king.danger[3] x").is_err());
+}
@@ -0,0 +1,161 @@
+use crate::{
+    board::Board,
+    core_types::{Color, Piece},
+};
+
+const fn xorshift(mut x: u64) -> u64 {
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    x.wrapping_mul(0x2545F4914F6CDD1D)
+}
+
+/// `[color][piece][square]`
+pub const PIECE_KEYS: [[[u64; 64]; 6]; 2] = {
+    let mut keys = [[[0; 64]; 6]; 2];
+    let mut seed = 0x9E3779B97F4A7C15;
+    let mut c = 0;
+    while c < 2 {
+        let mut p = 0;
+        while p < 6 {
+            let mut sq = 0;
+            while sq < 64 {
+                seed = xorshift(seed);
+                keys[c][p][sq] = seed;
+                sq += 1;
+            }
+            p += 1;
+        }
+        c += 1;
+    }
+    keys
+};
+pub const CASTLE_KEYS: [u64; 16] = {
+    let mut keys = [0; 16];
+    let mut seed = 0xD1B54A32D192ED03;
+    let mut i = 1;
+    while i < 16 {
+        seed = xorshift(seed);
+        keys[i] = seed;
+        i += 1;
+    }
+    keys
+};
+pub const EP_KEYS: [u64; 8] = {
+    let mut keys = [0; 8];
+    let mut seed = 0x8CB92BA72F3D8DD7;
+    let mut i = 0;
+    while i < 8 {
+        seed = xorshift(seed);
+        keys[i] = seed;
+        i += 1;
+    }
+    keys
+};
+pub const BLACK_TO_MOVE_KEY: u64 = xorshift(0xF1357AEA2E62A9C5);
+
+impl Board {
+    #[allow(clippy::needless_range_loop)]
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0;
+        for c in 0..2 {
+            for p in 0..6 {
+                for sq in self.pieces[p] & self.side[c] {
+                    hash ^= PIECE_KEYS[c][p][sq.as_idx().0 as usize];
+                }
+            }
+        }
+        hash ^ self.state_key()
+    }
+
+    /// Key of everything but the pieces: castling, en passant and side to move
+    fn state_key(&self) -> u64 {
+        let mut hash = CASTLE_KEYS[(self.castle & 0b1111) as usize];
+        // fen loading uses 0 for "no ep square", make_move uses 255
+        if self.ep.valid() && self.ep.0 != 0 {
+            hash ^= EP_KEYS[(self.ep.0 % 8) as usize];
+        }
+        if self.color == Color::Black {
+            hash ^= BLACK_TO_MOVE_KEY;
+        }
+        hash
+    }
+
+    /// Key of the pawns alone, for the pawn hash table
+    pub fn pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (side, keys) in self.side.iter().zip(PIECE_KEYS.iter()) {
+            for sq in self.pieces[Piece::Pawn as usize] & *side {
+                hash ^= keys[Piece::Pawn as usize][sq.as_idx().0 as usize];
+            }
+        }
+        hash
+    }
+}
+
+/// The full and the pawn key of a position, kept up to date move by move like
+/// `Psqt` so the search doesn't hash the whole board at every node
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Keys {
+    pub hash: u64,
+    pub pawns: u64,
+}
+
+impl Keys {
+    pub fn new(b: &Board) -> Self {
+        Keys {
+            hash: b.hash(),
+            pawns: b.pawn_hash(),
+        }
+    }
+
+    /// Applies the move, or null move, that turned `before` into `after`, by
+    /// the squares that changed like `Psqt::update`
+    pub fn update(&mut self, before: &Board, after: &Board) {
+        for color in [Color::White, Color::Black] {
+            let c = color as usize;
+            for (p, keys) in PIECE_KEYS[c].iter().enumerate() {
+                let old = before.pieces[p] & before.side[c];
+                let new = after.pieces[p] & after.side[c];
+                for sq in old ^ new {
+                    let key = keys[sq.as_idx().0 as usize];
+                    self.hash ^= key;
+                    if p == Piece::Pawn as usize {
+                        self.pawns ^= key;
+                    }
+                }
+            }
+        }
+        self.hash ^= before.state_key() ^ after.state_key();
+    }
+}
+
+#[test]
+fn test_hash_transposition() {
+    crate::attacks::init_magics(false);
+    let mut a = Board::new();
+    crate::fen::load_fen(
+        &mut a,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    )
+    .unwrap();
+    let mut b = a;
+    a.make_move_list("g1f3 g8f6 b1c3");
+    b.make_move_list("b1c3 g8f6 g1f3");
+    assert_eq!(a.hash(), b.hash());
+    b.make_move_list("f6g8");
+    assert_ne!(a.hash(), b.hash());
+
+    // Updated keys match the ones from scratch, after null moves too
+    crate::utils::walk_moves(3, Keys::new, &mut |before, after, keys| {
+        let mut keys = keys;
+        keys.update(before, after);
+        assert_eq!(keys, Keys::new(after));
+        let mut null_b = *after;
+        null_b.make_null_move();
+        let mut null_keys = keys;
+        null_keys.update(after, &null_b);
+        assert_eq!(null_keys, Keys::new(&null_b));
+        keys
+    });
+}